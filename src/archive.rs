@@ -0,0 +1,747 @@
+// Copyright 2018 rust-multipart-rfc7578 Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+//
+
+use crate::{dir::DirOptions, error::Error};
+use flate2::{read::DeflateEncoder, read::GzEncoder, Compression, CrcReader};
+use mime::Mime;
+use std::{
+    fs::{self, File},
+    io::{self, Read},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The size of a tar block.
+///
+const BLOCK: u64 = 512;
+
+/// The format of a directory streamed as a part by
+/// [`Form::add_archive`](struct.Form.html#method.add_archive).
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ArchiveFormat {
+    /// An uncompressed tar archive. Its length is known up front.
+    ///
+    Tar,
+
+    /// A gzip-compressed tar archive.
+    ///
+    TarGzip,
+
+    /// A zip archive storing files uncompressed. Its length is known up
+    /// front. Every file is read twice while the archive is written out,
+    /// once for the CRC in its header and once for its data.
+    ///
+    Zip,
+
+    /// A zip archive compressing files with deflate.
+    ///
+    ZipDeflated,
+}
+
+impl ArchiveFormat {
+    /// Returns the extension of archive files in this format.
+    ///
+    #[inline]
+    pub(crate) fn extension(self) -> &'static str {
+        match self {
+            ArchiveFormat::Tar => "tar",
+            ArchiveFormat::TarGzip => "tar.gz",
+            ArchiveFormat::Zip | ArchiveFormat::ZipDeflated => "zip",
+        }
+    }
+
+    /// Returns the content type of archive files in this format.
+    ///
+    pub(crate) fn content_type(self) -> Mime {
+        match self {
+            ArchiveFormat::Tar => "application/x-tar",
+            ArchiveFormat::TarGzip => "application/gzip",
+            ArchiveFormat::Zip | ArchiveFormat::ZipDeflated => "application/zip",
+        }
+        .parse()
+        .expect("valid content types")
+    }
+}
+
+/// A file in an archive.
+///
+struct Entry {
+    path: PathBuf,
+    name: String,
+    size: u64,
+    modified: SystemTime,
+
+    /// The tar header, including a long name entry if needed.
+    ///
+    tar_header: Vec<u8>,
+}
+
+/// The files of a directory, listed when the part is added, and streamed
+/// as an archive every time the form is written out.
+///
+#[derive(Clone)]
+pub(crate) struct Archive {
+    format: ArchiveFormat,
+    entries: Arc<[Entry]>,
+}
+
+impl Archive {
+    pub(crate) fn new(
+        root: &Path,
+        format: ArchiveFormat,
+        options: &DirOptions,
+    ) -> Result<Self, Error> {
+        let mut builder = tar::Builder::new(vec![]);
+        let mut entries = vec![];
+        for (path, name) in options.walk(root)? {
+            let open_error = |source| Error::Open {
+                path: path.clone(),
+                source,
+            };
+            let meta = fs::metadata(&path).map_err(open_error)?;
+            let modified = meta.modified().unwrap_or(UNIX_EPOCH);
+
+            let start = builder.get_ref().len();
+            if let ArchiveFormat::Tar | ArchiveFormat::TarGzip = format {
+                let mut header = tar::Header::new_gnu();
+                header.set_metadata(&meta);
+                builder
+                    .append_data(&mut header, &name, io::empty())
+                    .map_err(open_error)?;
+            }
+            entries.push(Entry {
+                size: meta.len(),
+                modified,
+                tar_header: builder.get_ref()[start..].to_vec(),
+                path,
+                name,
+            });
+        }
+
+        let archive = Self {
+            format,
+            entries: entries.into(),
+        };
+        if let ArchiveFormat::Zip | ArchiveFormat::ZipDeflated = format {
+            // Without ZIP64, sizes and offsets are limited to 32 bits.
+            //
+            if archive.zip_len() > u64::from(u32::MAX) || archive.entries.len() > 0xffff {
+                return Err(Error::Archive {
+                    path: root.to_path_buf(),
+                    message: "too large for a zip archive".to_string(),
+                });
+            }
+        }
+        Ok(archive)
+    }
+
+    /// Returns the length of the archive, if it is not compressed.
+    ///
+    pub(crate) fn len(&self) -> Option<u64> {
+        match self.format {
+            ArchiveFormat::Tar => Some(
+                self.entries
+                    .iter()
+                    .map(|entry| entry.tar_header.len() as u64 + padded(entry.size))
+                    .sum::<u64>()
+                    + 2 * BLOCK,
+            ),
+            ArchiveFormat::Zip => Some(self.zip_len()),
+            ArchiveFormat::TarGzip | ArchiveFormat::ZipDeflated => None,
+        }
+    }
+
+    /// Returns the length of a zip archive storing the files.
+    ///
+    fn zip_len(&self) -> u64 {
+        let descriptor = match self.format {
+            ArchiveFormat::ZipDeflated => DESCRIPTOR,
+            _ => 0,
+        };
+        self.entries
+            .iter()
+            .map(|entry| {
+                LOCAL_HEADER
+                    + descriptor
+                    + CENTRAL_HEADER
+                    + 2 * entry.name.len() as u64
+                    + entry.size
+            })
+            .sum::<u64>()
+            + END_OF_CENTRAL_DIRECTORY
+    }
+
+    /// Returns a new reader streaming the archive.
+    ///
+    pub(crate) fn reader(&self) -> Box<dyn Read + Send> {
+        match self.format {
+            ArchiveFormat::Tar => Box::new(TarReader::new(self.entries.clone())),
+            ArchiveFormat::TarGzip => Box::new(GzEncoder::new(
+                TarReader::new(self.entries.clone()),
+                Compression::default(),
+            )),
+            ArchiveFormat::Zip => Box::new(ZipReader::new(self.entries.clone(), false)),
+            ArchiveFormat::ZipDeflated => Box::new(ZipReader::new(self.entries.clone(), true)),
+        }
+    }
+}
+
+/// Rounds a size up to whole tar blocks.
+///
+#[inline]
+fn padded(size: u64) -> u64 {
+    size.div_ceil(BLOCK) * BLOCK
+}
+
+/// Copies the rest of `src` from `pos` into `buf`.
+///
+#[inline]
+fn copy_from(src: &[u8], pos: &mut usize, buf: &mut [u8]) -> usize {
+    let n = (src.len() - *pos).min(buf.len());
+    buf[..n].copy_from_slice(&src[*pos..*pos + n]);
+    *pos += n;
+    n
+}
+
+/// The data of a file, opened on the first read. A file that shrank since
+/// it was listed is an error, and one that grew is cut to its listed size
+/// so the archive stays valid.
+///
+struct FileData {
+    path: PathBuf,
+    size: u64,
+    read: u64,
+    file: Option<io::Take<File>>,
+}
+
+impl FileData {
+    #[inline]
+    fn new(entry: &Entry) -> Self {
+        Self {
+            path: entry.path.clone(),
+            size: entry.size,
+            read: 0,
+            file: None,
+        }
+    }
+}
+
+impl Read for FileData {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.file.is_none() {
+            let file = File::open(&self.path).map_err(|source| Error::Open {
+                path: self.path.clone(),
+                source,
+            })?;
+            self.file = Some(file.take(self.size));
+        }
+        let n = match self.file {
+            Some(ref mut file) => file.read(buf)?,
+            None => 0,
+        };
+        self.read += n as u64;
+        if n == 0 && !buf.is_empty() && self.read < self.size {
+            return Err(Error::FileChanged {
+                path: self.path.clone(),
+                expected: self.size,
+                actual: self.read,
+            }
+            .into());
+        }
+        Ok(n)
+    }
+}
+
+enum TarStage {
+    Header(usize),
+    Data(FileData),
+    Padding(usize),
+    End(usize),
+}
+
+/// Streams a tar archive: a header, the data and its padding for every
+/// file, and two empty blocks at the end.
+///
+struct TarReader {
+    entries: Arc<[Entry]>,
+    index: usize,
+    stage: TarStage,
+}
+
+impl TarReader {
+    #[inline]
+    fn new(entries: Arc<[Entry]>) -> Self {
+        let stage = if entries.is_empty() {
+            TarStage::End(0)
+        } else {
+            TarStage::Header(0)
+        };
+        Self {
+            entries,
+            index: 0,
+            stage,
+        }
+    }
+}
+
+impl TarReader {
+    /// Moves on to the stage after the current one.
+    ///
+    fn advance(&mut self) {
+        let entry = &self.entries[self.index];
+        self.stage = match self.stage {
+            TarStage::Header(_) => TarStage::Data(FileData::new(entry)),
+            TarStage::Data(_) => {
+                TarStage::Padding((BLOCK - (padded(entry.size) - entry.size)) as usize)
+            }
+            _ => {
+                self.index += 1;
+                if self.index < self.entries.len() {
+                    TarStage::Header(0)
+                } else {
+                    TarStage::End(0)
+                }
+            }
+        };
+    }
+}
+
+impl Read for TarReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        const ZEROS: [u8; 2 * BLOCK as usize] = [0; 2 * BLOCK as usize];
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            let n = match self.stage {
+                TarStage::Header(ref mut pos) => {
+                    copy_from(&self.entries[self.index].tar_header, pos, buf)
+                }
+                TarStage::Data(ref mut data) => data.read(buf)?,
+                TarStage::Padding(ref mut pos) => copy_from(&ZEROS[..BLOCK as usize], pos, buf),
+                TarStage::End(ref mut pos) => return Ok(copy_from(&ZEROS, pos, buf)),
+            };
+            if n > 0 {
+                return Ok(n);
+            }
+            self.advance();
+        }
+    }
+}
+
+/// The sizes of the fixed parts of zip records.
+///
+const LOCAL_HEADER: u64 = 30;
+const DESCRIPTOR: u64 = 16;
+const CENTRAL_HEADER: u64 = 46;
+const END_OF_CENTRAL_DIRECTORY: u64 = 22;
+
+/// The sizes and CRC of a file are written after its data.
+///
+const DATA_DESCRIPTOR: u16 = 0x0008;
+
+/// Names are UTF-8.
+///
+const UTF8_NAMES: u16 = 0x0800;
+
+/// What the central directory records about a file once it is written.
+///
+struct ZipRecord {
+    offset: u32,
+    crc: u32,
+    compressed: u32,
+}
+
+enum ZipData {
+    Stored(CrcReader<FileData>),
+    Deflated(DeflateEncoder<CrcReader<FileData>>),
+}
+
+impl ZipData {
+    fn crc(&self) -> u32 {
+        match *self {
+            ZipData::Stored(ref read) => read.crc().sum(),
+            ZipData::Deflated(ref read) => read.get_ref().crc().sum(),
+        }
+    }
+}
+
+enum ZipStage {
+    /// The header of the entry at the index is written next, or the
+    /// central directory after the last one.
+    ///
+    Next,
+
+    /// The local header, and the CRC it carries for a stored file.
+    ///
+    Header(Vec<u8>, usize, u32),
+    Data(ZipData, u64, u32),
+    Descriptor(Vec<u8>, usize),
+    Central(Vec<u8>, usize),
+}
+
+/// Streams a zip archive without seeking.
+///
+/// The local header of a stored file carries its sizes and CRC, so
+/// streaming readers can extract it. The file is read once for the CRC and
+/// once for its data. A deflated file is read only once, and its sizes and
+/// CRC follow its data in a descriptor.
+///
+struct ZipReader {
+    entries: Arc<[Entry]>,
+    deflate: bool,
+    index: usize,
+    offset: u64,
+    records: Vec<ZipRecord>,
+    stage: ZipStage,
+}
+
+impl ZipReader {
+    fn new(entries: Arc<[Entry]>, deflate: bool) -> Self {
+        Self {
+            entries,
+            deflate,
+            index: 0,
+            offset: 0,
+            records: vec![],
+            stage: ZipStage::Next,
+        }
+    }
+
+    #[inline]
+    fn method(&self) -> u16 {
+        if self.deflate {
+            8
+        } else {
+            0
+        }
+    }
+
+    #[inline]
+    fn flags(&self) -> u16 {
+        if self.deflate {
+            DATA_DESCRIPTOR | UTF8_NAMES
+        } else {
+            UTF8_NAMES
+        }
+    }
+
+    /// Starts the entry at `index`, or the central directory after the
+    /// last one.
+    ///
+    fn next_entry(&self) -> io::Result<ZipStage> {
+        let entry = match self.entries.get(self.index) {
+            Some(entry) => entry,
+            None => return Ok(ZipStage::Central(self.central_directory(), 0)),
+        };
+        let crc = if self.deflate {
+            0
+        } else {
+            let mut data = CrcReader::new(FileData::new(entry));
+            io::copy(&mut data, &mut io::sink())?;
+            data.crc().sum()
+        };
+        let (time, date) = dos_time(entry.modified);
+        let mut header = vec![];
+        put32(&mut header, 0x0403_4b50);
+        put16(&mut header, 20);
+        put16(&mut header, self.flags());
+        put16(&mut header, self.method());
+        put16(&mut header, time);
+        put16(&mut header, date);
+        if self.deflate {
+            header.extend_from_slice(&[0; 12]);
+        } else {
+            put32(&mut header, crc);
+            put32(&mut header, entry.size as u32);
+            put32(&mut header, entry.size as u32);
+        }
+        put16(&mut header, entry.name.len() as u16);
+        put16(&mut header, 0);
+        header.extend_from_slice(entry.name.as_bytes());
+        Ok(ZipStage::Header(header, 0, crc))
+    }
+
+    fn central_directory(&self) -> Vec<u8> {
+        let mut central = vec![];
+        for (entry, record) in self.entries.iter().zip(&self.records) {
+            let (time, date) = dos_time(entry.modified);
+            put32(&mut central, 0x0201_4b50);
+            put16(&mut central, 20);
+            put16(&mut central, 20);
+            put16(&mut central, self.flags());
+            put16(&mut central, self.method());
+            put16(&mut central, time);
+            put16(&mut central, date);
+            put32(&mut central, record.crc);
+            put32(&mut central, record.compressed);
+            put32(&mut central, entry.size as u32);
+            put16(&mut central, entry.name.len() as u16);
+            central.extend_from_slice(&[0; 12]);
+            put32(&mut central, record.offset);
+            central.extend_from_slice(entry.name.as_bytes());
+        }
+        let count = self.records.len() as u16;
+        let size = central.len() as u32;
+        put32(&mut central, 0x0605_4b50);
+        put32(&mut central, 0);
+        put16(&mut central, count);
+        put16(&mut central, count);
+        put32(&mut central, size);
+        put32(&mut central, self.offset as u32);
+        put16(&mut central, 0);
+        central
+    }
+
+    /// Moves on to the stage after the current one.
+    ///
+    fn advance(&mut self) -> io::Result<()> {
+        let entries = self.entries.clone();
+        self.stage = match self.stage {
+            ZipStage::Next => self.next_entry()?,
+            ZipStage::Header(_, _, crc) => {
+                let data = CrcReader::new(FileData::new(&entries[self.index]));
+                let data = if self.deflate {
+                    ZipData::Deflated(DeflateEncoder::new(data, Compression::default()))
+                } else {
+                    ZipData::Stored(data)
+                };
+                ZipStage::Data(data, 0, crc)
+            }
+            ZipStage::Data(ref data, compressed, crc) => {
+                let entry = &entries[self.index];
+                let record = ZipRecord {
+                    offset: (self.offset - compressed - LOCAL_HEADER - entry.name.len() as u64)
+                        as u32,
+                    crc: data.crc(),
+                    compressed: compressed as u32,
+                };
+                if self.deflate {
+                    let mut descriptor = vec![];
+                    put32(&mut descriptor, 0x0807_4b50);
+                    put32(&mut descriptor, record.crc);
+                    put32(&mut descriptor, record.compressed);
+                    put32(&mut descriptor, entry.size as u32);
+                    self.records.push(record);
+                    ZipStage::Descriptor(descriptor, 0)
+                } else if record.crc != crc {
+                    return Err(Error::Archive {
+                        path: entry.path.clone(),
+                        message: "changed while it was archived".to_string(),
+                    }
+                    .into());
+                } else {
+                    self.records.push(record);
+                    self.index += 1;
+                    ZipStage::Next
+                }
+            }
+            _ => {
+                self.index += 1;
+                ZipStage::Next
+            }
+        };
+        Ok(())
+    }
+
+    /// Fails once an offset no longer fits a zip without ZIP64, which can
+    /// happen when files grow while deflated.
+    ///
+    fn check_offset(&self) -> io::Result<()> {
+        if self.offset > u64::from(u32::MAX) {
+            let path = self.entries.get(self.index).map(|entry| entry.path.clone());
+            return Err(Error::Archive {
+                path: path.unwrap_or_default(),
+                message: "too large for a zip archive".to_string(),
+            }
+            .into());
+        }
+        Ok(())
+    }
+}
+
+impl Read for ZipReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            let n = match self.stage {
+                ZipStage::Next => 0,
+                ZipStage::Header(ref header, ref mut pos, _) => copy_from(header, pos, buf),
+                ZipStage::Data(ref mut data, ref mut compressed, _) => {
+                    let n = match *data {
+                        ZipData::Stored(ref mut read) => read.read(buf)?,
+                        ZipData::Deflated(ref mut read) => read.read(buf)?,
+                    };
+                    *compressed += n as u64;
+                    n
+                }
+                ZipStage::Descriptor(ref descriptor, ref mut pos) => {
+                    copy_from(descriptor, pos, buf)
+                }
+                ZipStage::Central(ref central, ref mut pos) => {
+                    return Ok(copy_from(central, pos, buf));
+                }
+            };
+            self.offset += n as u64;
+            self.check_offset()?;
+            if n > 0 {
+                return Ok(n);
+            }
+            self.advance()?;
+        }
+    }
+}
+
+#[inline]
+fn put16(buf: &mut Vec<u8>, value: u16) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+#[inline]
+fn put32(buf: &mut Vec<u8>, value: u32) {
+    buf.extend_from_slice(&value.to_le_bytes());
+}
+
+/// Converts a time to the MS-DOS time and date of zip records, in UTC.
+/// Times before 1980 are clamped to its start.
+///
+fn dos_time(time: SystemTime) -> (u16, u16) {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or(0);
+    let days = (secs / 86_400) as i64;
+    let secs = secs % 86_400;
+
+    // Converts days since the epoch to a civil date.
+    //
+    // [See](http://howardhinnant.github.io/date_algorithms.html#civil_from_days)
+    //
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    if year < 1980 {
+        return (0, (1 << 5) | 1);
+    }
+    let time = ((secs / 3600) << 11) | ((secs % 3600 / 60) << 5) | ((secs % 60) / 2);
+    let date = ((year - 1980).min(127) << 9) | (month << 5) | day;
+    (time as u16, date as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{dos_time, Archive, ArchiveFormat};
+    use crate::DirOptions;
+    use flate2::read::GzDecoder;
+    use std::{
+        fs,
+        io::{Cursor, Read},
+        path::Path,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    fn read_archive(format: ArchiveFormat) -> (Option<u64>, Vec<u8>) {
+        let options = DirOptions::default().include("*.rs").unwrap();
+        let archive = Archive::new(Path::new("src/bin"), format, &options).unwrap();
+        let mut data = vec![];
+        archive.reader().read_to_end(&mut data).unwrap();
+        (archive.len(), data)
+    }
+
+    fn tar_entries<R: Read>(read: R) -> Vec<(String, Vec<u8>)> {
+        tar::Archive::new(read)
+            .entries()
+            .unwrap()
+            .map(|entry| {
+                let mut entry = entry.unwrap();
+                let mut data = vec![];
+                entry.read_to_end(&mut data).unwrap();
+                (entry.path().unwrap().to_string_lossy().into_owned(), data)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_tar() {
+        let expected = vec![(
+            "bin/multipart.rs".to_string(),
+            fs::read("src/bin/multipart.rs").unwrap(),
+        )];
+
+        let (len, data) = read_archive(ArchiveFormat::Tar);
+        assert_eq!(Some(data.len() as u64), len);
+        assert_eq!(expected, tar_entries(&data[..]));
+
+        let (len, data) = read_archive(ArchiveFormat::TarGzip);
+        assert_eq!(None, len);
+        assert_eq!(expected, tar_entries(GzDecoder::new(&data[..])));
+    }
+
+    fn zip_entries(data: &[u8]) -> Vec<(String, Vec<u8>)> {
+        let mut archive = zip::ZipArchive::new(Cursor::new(data)).unwrap();
+        (0..archive.len())
+            .map(|i| {
+                let mut file = archive.by_index(i).unwrap();
+                let mut data = vec![];
+                file.read_to_end(&mut data).unwrap();
+                (file.name().to_string(), data)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_zip() {
+        let expected = vec![(
+            "bin/multipart.rs".to_string(),
+            fs::read("src/bin/multipart.rs").unwrap(),
+        )];
+
+        let (len, data) = read_archive(ArchiveFormat::Zip);
+        assert_eq!(Some(data.len() as u64), len);
+        assert_eq!(expected, zip_entries(&data));
+
+        // Stored files can be extracted without the central directory.
+        //
+        let mut read = &data[..];
+        let mut file = zip::read::read_zipfile_from_stream(&mut read)
+            .unwrap()
+            .unwrap();
+        let mut streamed = vec![];
+        file.read_to_end(&mut streamed).unwrap();
+        assert_eq!(expected[0].0, file.name());
+        assert_eq!(expected[0].1, streamed);
+
+        let (len, data) = read_archive(ArchiveFormat::ZipDeflated);
+        assert_eq!(None, len);
+        assert_eq!(expected, zip_entries(&data));
+    }
+
+    #[test]
+    fn test_dos_time() {
+        assert_eq!((0, 0x21), dos_time(UNIX_EPOCH));
+        // 2018-07-04 12:34:56 UTC.
+        //
+        let time = UNIX_EPOCH + Duration::from_secs(1_530_707_696);
+        assert_eq!(
+            ((12 << 11) | (34 << 5) | 28, (38 << 9) | (7 << 5) | 4),
+            dos_time(time)
+        );
+    }
+}
@@ -0,0 +1,282 @@
+// Copyright 2018 rust-multipart-rfc7578 Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+//
+
+//! Encodes and decodes multipart/form-data bodies offline.
+//!
+//! ```sh
+//! multipart encode -F text=hello -F file=@report.pdf -o body.bin
+//! multipart decode --content-type "multipart/form-data; boundary=..." --out parts/ < body.bin
+//! multipart inspect --content-type "multipart/form-data; boundary=..." < body.bin
+//! ```
+//!
+
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use multipart_rfc7578::{Error, Form, FormParser, ParsedPart, Profile};
+use serde_json::{json, Value};
+use std::{
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::Path,
+    process,
+};
+
+fn main() {
+    let matches = command().get_matches();
+    let result = match matches.subcommand() {
+        Some(("encode", matches)) => encode(matches),
+        Some(("decode", matches)) => decode(matches),
+        Some(("inspect", matches)) => inspect(matches),
+        _ => unreachable!("a subcommand is required"),
+    };
+    if let Err(e) = result {
+        eprintln!("multipart: {}", e);
+        process::exit(1);
+    }
+}
+
+fn command() -> Command {
+    let content_type = Arg::new("content-type")
+        .long("content-type")
+        .short('t')
+        .required(true)
+        .help("The content type of the body, with its boundary");
+    let input = Arg::new("input")
+        .long("input")
+        .short('i')
+        .help("Reads the body from a file instead of stdin");
+
+    Command::new("multipart")
+        .about("Encodes and decodes multipart/form-data bodies")
+        .subcommand_required(true)
+        .arg_required_else_help(true)
+        .subcommand(
+            Command::new("encode")
+                .about("Writes out a form, and prints its Content-Type and length")
+                .arg(
+                    Arg::new("form")
+                        .long("form")
+                        .short('F')
+                        .action(ArgAction::Append)
+                        .help("Adds a field, in the syntax of `curl -F`"),
+                )
+                .arg(
+                    Arg::new("form-string")
+                        .long("form-string")
+                        .action(ArgAction::Append)
+                        .help("Adds a literal text field, as NAME=VALUE"),
+                )
+                .arg(
+                    Arg::new("output")
+                        .long("output")
+                        .short('o')
+                        .help("Writes the body to a file instead of stdout"),
+                )
+                .arg(
+                    Arg::new("profile")
+                        .long("profile")
+                        .value_parser(["default", "chrome", "firefox", "curl"])
+                        .default_value("default")
+                        .help("Formats the body like a client"),
+                ),
+        )
+        .subcommand(
+            Command::new("decode")
+                .about("Extracts each part to a file, with a manifest.json")
+                .arg(content_type.clone())
+                .arg(input.clone())
+                .arg(
+                    Arg::new("out")
+                        .long("out")
+                        .required(true)
+                        .help("The directory to write the parts to"),
+                ),
+        )
+        .subcommand(
+            Command::new("inspect")
+                .about("Summarizes the parts of a body")
+                .arg(content_type)
+                .arg(input)
+                .arg(
+                    Arg::new("json")
+                        .long("json")
+                        .action(ArgAction::SetTrue)
+                        .help("Prints the summary as JSON"),
+                ),
+        )
+}
+
+fn encode(matches: &ArgMatches) -> Result<(), Error> {
+    let profile = match matches.get_one::<String>("profile").map(String::as_str) {
+        Some("chrome") => Profile::chrome(),
+        Some("firefox") => Profile::firefox(),
+        Some("curl") => Profile::curl(),
+        _ => Profile::default(),
+    };
+    let mut form = Form::with_profile(profile);
+
+    // Fields are added in the order they were given, across both options.
+    //
+    let mut fields = vec![];
+    for id in &["form", "form-string"] {
+        if let (Some(values), Some(indices)) =
+            (matches.get_many::<String>(id), matches.indices_of(id))
+        {
+            fields.extend(indices.zip(values.map(|value| (*id, value))));
+        }
+    }
+    fields.sort_by_key(|(index, _)| *index);
+    for (_, (id, value)) in fields {
+        if id == "form" {
+            form.add_curl_spec(value)?;
+        } else {
+            let (name, text) = value.split_once('=').ok_or_else(|| Error::Spec {
+                spec: value.clone(),
+                position: value.len(),
+                message: "expected `=` after the field name".to_string(),
+            })?;
+            form.add_text(name, text);
+        }
+    }
+
+    let content_type = form.content_type();
+    let mut reader = form.into_reader();
+    let (length, mut summary): (u64, Box<dyn Write>) = match matches.get_one::<String>("output") {
+        Some(path) => {
+            let mut file = File::create(path).map_err(|source| Error::Open {
+                path: path.into(),
+                source,
+            })?;
+            (io::copy(&mut reader, &mut file)?, Box::new(io::stdout()))
+        }
+        None => (
+            io::copy(&mut reader, &mut io::stdout().lock())?,
+            Box::new(io::stderr()),
+        ),
+    };
+    writeln!(summary, "Content-Type: {}", content_type)?;
+    writeln!(summary, "Content-Length: {}", length)?;
+    Ok(())
+}
+
+fn decode(matches: &ArgMatches) -> Result<(), Error> {
+    let (content_type, parts) = parse(matches)?;
+    let out = Path::new(matches.get_one::<String>("out").expect("required"));
+    fs::create_dir_all(out)?;
+
+    let mut entries = vec![];
+    for (index, part) in parts.into_iter().enumerate() {
+        let filename = format!(
+            "{}-{}",
+            index,
+            sanitize(part.filename().unwrap_or_else(|| part.name()))
+        );
+        let mut entry = summary(index, &part);
+        entry["path"] = json!(filename);
+        fs::write(out.join(&filename), part.data())?;
+        entries.push(entry);
+    }
+    let manifest = json!({ "content_type": content_type, "parts": entries });
+    fs::write(
+        out.join("manifest.json"),
+        serde_json::to_string_pretty(&manifest).expect("JSON values serialize"),
+    )?;
+    Ok(())
+}
+
+fn inspect(matches: &ArgMatches) -> Result<(), Error> {
+    let (_, parts) = parse(matches)?;
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    if matches.get_flag("json") {
+        let entries: Vec<_> = parts
+            .iter()
+            .enumerate()
+            .map(|(index, part)| summary(index, part))
+            .collect();
+        let json = serde_json::to_string_pretty(&entries).expect("JSON values serialize");
+        writeln!(stdout, "{}", json)?;
+        return Ok(());
+    }
+    for (index, part) in parts.iter().enumerate() {
+        write!(stdout, "{}\t{}", index, part.name())?;
+        if let Some(filename) = part.filename() {
+            write!(stdout, "\tfilename={}", filename)?;
+        }
+        if let Some(content_type) = part.content_type() {
+            write!(stdout, "\ttype={}", content_type)?;
+        }
+        writeln!(stdout, "\t{} bytes", part.data().len())?;
+    }
+    Ok(())
+}
+
+/// Reads and parses the body given to `decode` or `inspect`.
+///
+fn parse(matches: &ArgMatches) -> Result<(String, Vec<ParsedPart>), Error> {
+    let content_type = matches
+        .get_one::<String>("content-type")
+        .expect("required")
+        .clone();
+    let parser = FormParser::new(&content_type)?;
+    let read: Box<dyn Read> = match matches.get_one::<String>("input") {
+        Some(path) => Box::new(File::open(path).map_err(|source| Error::Open {
+            path: path.into(),
+            source,
+        })?),
+        None => Box::new(io::stdin()),
+    };
+    Ok((content_type, parser.parse(read)?))
+}
+
+fn summary(index: usize, part: &ParsedPart) -> Value {
+    json!({
+        "index": index,
+        "name": part.name(),
+        "filename": part.filename(),
+        "content_type": part.content_type().map(ToString::to_string),
+        "headers": part.headers(),
+        "size": part.data().len(),
+    })
+}
+
+/// Turns a field name or filename into a file name that stays inside the
+/// output directory.
+///
+fn sanitize(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '_' | '-' => c,
+            _ => '_',
+        })
+        .collect();
+    let name = name.trim_start_matches('.');
+    if name.is_empty() {
+        "part".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{command, sanitize};
+
+    #[test]
+    fn test_command() {
+        command().debug_assert();
+    }
+
+    #[test]
+    fn test_sanitize() {
+        assert_eq!("report.pdf", sanitize("report.pdf"));
+        assert_eq!("_etc_passwd", sanitize("/etc/passwd"));
+        assert_eq!("_.._a", sanitize("/../a"));
+        assert_eq!("part", sanitize(".."));
+    }
+}
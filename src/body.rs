@@ -7,23 +7,336 @@
 // copied, modified, or distributed except according to those terms.
 //
 
+#[cfg(any(
+    feature = "md5",
+    feature = "sha1",
+    feature = "sha256",
+    feature = "crc32c"
+))]
+use crate::checksum::{self, Algorithm, BodyDigest, Checksum, Checksums, Hasher};
+use crate::chunk::{self, ChunkPipe};
 use crate::form::Form;
+use crate::throttle::{Delay, RateLimiter};
 use bytes::{BufMut, Bytes, BytesMut};
-use futures::{stream::Stream, Async, Poll};
+use futures::{stream::Stream, Async, Future, Poll};
 #[cfg(feature = "hyper")]
 use hyper::body::Payload;
 use std::io::{self, Read};
 
+/// The chunk size used by `Body::from`.
+///
+const DEFAULT_CHUNK_SIZE: usize = 2048;
+
 /// Multipart body that is compatible with Hyper and Actix-web.
 ///
+/// The stream fails with an `io::Error`, as expected by both clients. It
+/// wraps an [`Error`](enum.Error.html) naming the part that failed, which
+/// `Error::from` recovers.
+///
 pub struct Body<'a> {
     /// The amount of data to write with each chunk.
     ///
-    buf_size: usize,
+    chunk_size: ChunkSize,
+
+    /// The buffer chunks are read into. Chunks are read into its spare
+    /// capacity and split off the front of it, so its allocation is shared
+    /// with the chunks until they are dropped.
+    ///
+    buf: BytesMut,
+
+    /// The total length of the body, if every part has a known length.
+    ///
+    content_length: Option<u64>,
+
+    /// The number of bytes that have not been emitted yet, if the total
+    /// length is known.
+    ///
+    remaining: Option<u64>,
+
+    /// Set once the reader is exhausted.
+    ///
+    finished: bool,
+
+    /// Limits the rate at which chunks are emitted.
+    ///
+    rate_limiter: Option<RateLimiter>,
+
+    /// A chunk held back by the rate limiter, and the delay until it can
+    /// be emitted.
+    ///
+    pending: Option<(Bytes, Delay)>,
+
+    /// The checksums of the parts, recorded while they are emitted.
+    ///
+    #[cfg(any(
+        feature = "md5",
+        feature = "sha1",
+        feature = "sha256",
+        feature = "crc32c"
+    ))]
+    checksums: Checksums,
+
+    /// Hashes the emitted bytes, if a digest of the body was requested.
+    ///
+    #[cfg(any(
+        feature = "md5",
+        feature = "sha1",
+        feature = "sha256",
+        feature = "crc32c"
+    ))]
+    hasher: Option<Hasher>,
+
+    /// The algorithm of the digest, unless it is keyed.
+    ///
+    #[cfg(any(
+        feature = "md5",
+        feature = "sha1",
+        feature = "sha256",
+        feature = "crc32c"
+    ))]
+    digest_algorithm: Option<Algorithm>,
+
+    /// The digest of the body, once it is finished.
+    ///
+    #[cfg(any(
+        feature = "md5",
+        feature = "sha1",
+        feature = "sha256",
+        feature = "crc32c"
+    ))]
+    digest: Option<Vec<u8>>,
+
+    /// Set until hyper has polled the `Content-Digest` trailer, if the
+    /// body sends one.
+    ///
+    #[cfg(any(
+        feature = "md5",
+        feature = "sha1",
+        feature = "sha256",
+        feature = "crc32c"
+    ))]
+    trailers_pending: bool,
+
+    /// Receives the chunks of iterator parts, which are emitted as they
+    /// are rather than copied into the buffer.
+    ///
+    pipe: ChunkPipe,
 
     /// The reader.
     ///
-    reader: Box<'a + Read + Send>,
+    reader: Box<dyn 'a + Read + Send>,
+}
+
+impl<'a> Body<'a> {
+    /// Creates a builder to configure how a `Form` is turned into a `Body`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multipart_rfc7578::{Body, Form};
+    ///
+    /// let mut form = Form::default();
+    ///
+    /// form.add_text("text", "Hello World!");
+    /// let body = Body::builder().chunk_size(64 * 1024).build(form);
+    /// ```
+    ///
+    #[inline]
+    pub fn builder() -> BodyBuilder {
+        BodyBuilder::default()
+    }
+
+    /// Returns the total length of the body, as computed by
+    /// [`Form::content_length`](struct.Form.html#method.content_length).
+    ///
+    #[inline]
+    pub fn content_length(&self) -> Option<u64> {
+        self.content_length
+    }
+
+    /// Returns the number of bytes that have not been emitted yet, if the
+    /// total length is known.
+    ///
+    #[inline]
+    pub fn remaining(&self) -> Option<u64> {
+        self.remaining
+    }
+
+    /// Returns the bounds on the number of bytes left in the body, in the
+    /// same shape as `Iterator::size_hint`.
+    ///
+    #[inline]
+    pub fn size_hint(&self) -> (u64, Option<u64>) {
+        if self.finished {
+            (0, Some(0))
+        } else {
+            (self.remaining.unwrap_or(0), self.remaining)
+        }
+    }
+
+    /// Returns the checksums requested with
+    /// [`Form::set_checksum`](struct.Form.html#method.set_checksum) for
+    /// the parts that have been emitted so far, in the order they were
+    /// recorded. Once the body is finished, every checksum is available.
+    ///
+    #[cfg(any(
+        feature = "md5",
+        feature = "sha1",
+        feature = "sha256",
+        feature = "crc32c"
+    ))]
+    pub fn checksums(&self) -> Vec<Checksum> {
+        match self.checksums.lock() {
+            Ok(checksums) => checksums.clone(),
+            Err(poisoned) => poisoned.into_inner().clone(),
+        }
+    }
+
+    /// Returns the digest requested with
+    /// [`BodyBuilder::digest`](struct.BodyBuilder.html#method.digest) or
+    /// [`BodyBuilder::hmac_sha256`](struct.BodyBuilder.html#method.hmac_sha256),
+    /// once every chunk has been emitted.
+    ///
+    #[cfg(any(
+        feature = "md5",
+        feature = "sha1",
+        feature = "sha256",
+        feature = "crc32c"
+    ))]
+    #[inline]
+    pub fn digest(&self) -> Option<&[u8]> {
+        self.digest.as_ref().map(|digest| &digest[..])
+    }
+
+    /// Returns the value of a `Content-Digest` trailer for the body, once
+    /// every chunk has been emitted. Keyed digests are not included.
+    ///
+    #[cfg(any(
+        feature = "md5",
+        feature = "sha1",
+        feature = "sha256",
+        feature = "crc32c"
+    ))]
+    #[inline]
+    pub fn content_digest(&self) -> Option<String> {
+        match (self.digest_algorithm, self.digest.as_ref()) {
+            (Some(algorithm), Some(digest)) => Some(checksum::content_digest(algorithm, digest)),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the body will not emit any more chunks, nor a
+    /// `Content-Digest` trailer.
+    ///
+    #[inline]
+    pub fn is_end_stream(&self) -> bool {
+        (self.finished || self.remaining == Some(0)) && !self.trailers_pending()
+    }
+
+    #[cfg(any(
+        feature = "md5",
+        feature = "sha1",
+        feature = "sha256",
+        feature = "crc32c"
+    ))]
+    #[inline]
+    fn trailers_pending(&self) -> bool {
+        self.trailers_pending
+    }
+
+    #[cfg(not(any(
+        feature = "md5",
+        feature = "sha1",
+        feature = "sha256",
+        feature = "crc32c"
+    )))]
+    #[inline]
+    fn trailers_pending(&self) -> bool {
+        false
+    }
+
+    /// Finishes the digest of the body, once every byte has been hashed.
+    ///
+    #[cfg(any(
+        feature = "md5",
+        feature = "sha1",
+        feature = "sha256",
+        feature = "crc32c"
+    ))]
+    #[inline]
+    fn finish_digest(&mut self) {
+        if let Some(hasher) = self.hasher.take() {
+            self.digest = Some(hasher.finish());
+        }
+    }
+
+    /// Reads the next chunk, or `None` once the reader is exhausted.
+    ///
+    fn read_chunk(&mut self) -> io::Result<Option<Bytes>> {
+        let size = self.chunk_size.current;
+        self.buf.reserve(size);
+        let num = loop {
+            // The spare capacity is read into without being zeroed first.
+            //
+            let buf = unsafe { &mut self.buf.bytes_mut()[..size] };
+            let reader = &mut self.reader;
+            let read = chunk::with_pipe(Some(self.pipe.clone()), || reader.read(buf));
+            match read {
+                Ok(num) => break num,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    self.pipe.take();
+                    return Err(e);
+                }
+            }
+        };
+        if num == 0 {
+            self.finished = true;
+            #[cfg(any(
+                feature = "md5",
+                feature = "sha1",
+                feature = "sha256",
+                feature = "crc32c"
+            ))]
+            self.finish_digest();
+            return Ok(None);
+        }
+        self.chunk_size.adapt(num);
+        match self.pipe.take() {
+            Some(chunk) => Ok(Some(chunk)),
+            None => {
+                unsafe { self.buf.advance_mut(num) };
+                Ok(Some(self.buf.split_to(num).freeze()))
+            }
+        }
+    }
+
+    /// Accounts for an emitted chunk.
+    ///
+    #[inline]
+    fn emit(&mut self, chunk: Bytes) -> Poll<Option<Bytes>, io::Error> {
+        if let Some(remaining) = self.remaining.as_mut() {
+            *remaining = remaining.saturating_sub(chunk.len() as u64);
+        }
+        #[cfg(any(
+            feature = "md5",
+            feature = "sha1",
+            feature = "sha256",
+            feature = "crc32c"
+        ))]
+        {
+            if let Some(hasher) = self.hasher.as_mut() {
+                hasher.update(&chunk);
+            }
+            // A body of known length may not be read to its end, so the
+            // digest is finished with its last byte.
+            //
+            if self.remaining == Some(0) {
+                self.finish_digest();
+            }
+        }
+        Ok(Async::Ready(Some(chunk)))
+    }
 }
 
 impl<'a> Stream for Body<'a> {
@@ -34,18 +347,32 @@ impl<'a> Stream for Body<'a> {
     /// Iterate over each form part, and write it out.
     ///
     fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
-        let bytes = BytesMut::with_capacity(self.buf_size);
-        let mut writer = bytes.writer();
-        unsafe {
-            let buf = writer.get_mut();
-            let num = self.reader.read(&mut buf.bytes_mut())?;
-            if num == 0 {
-                return Ok(Async::Ready(None));
-            } else {
-                buf.advance_mut(num);
+        if let Some((chunk, mut delay)) = self.pending.take() {
+            return match delay.poll()? {
+                Async::Ready(()) => self.emit(chunk),
+                Async::NotReady => {
+                    self.pending = Some((chunk, delay));
+                    Ok(Async::NotReady)
+                }
+            };
+        }
+        let chunk = match self.read_chunk()? {
+            Some(chunk) => chunk,
+            None => return Ok(Async::Ready(None)),
+        };
+        let deadline = match self.rate_limiter {
+            Some(ref limiter) => limiter
+                .reserve(chunk.len())
+                .map(|deadline| limiter.delay(deadline)),
+            None => None,
+        };
+        match deadline {
+            Some(delay) => {
+                self.pending = Some((chunk, delay));
+                self.poll()
             }
+            None => self.emit(chunk),
         }
-        Ok(Async::Ready(Some(writer.into_inner().freeze())))
     }
 }
 
@@ -65,6 +392,48 @@ impl Payload for Body<'static> {
             Err(e) => Err(e),
         }
     }
+
+    /// Reports the number of bytes left, so hyper can use a fixed-length
+    /// encoding when the length of every part is known.
+    ///
+    #[inline]
+    fn content_length(&self) -> Option<u64> {
+        if self.finished {
+            Some(0)
+        } else {
+            self.remaining
+        }
+    }
+
+    /// Sends the digest requested with
+    /// [`BodyBuilder::digest`](struct.BodyBuilder.html#method.digest) as
+    /// a `Content-Digest` trailer.
+    ///
+    #[cfg(any(
+        feature = "md5",
+        feature = "sha1",
+        feature = "sha256",
+        feature = "crc32c"
+    ))]
+    fn poll_trailers(&mut self) -> Poll<Option<http::HeaderMap>, Self::Error> {
+        self.trailers_pending = false;
+        let mut trailers = http::HeaderMap::new();
+        if let Some(value) = self.content_digest() {
+            let value = http::header::HeaderValue::from_str(&value)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            trailers.insert("content-digest", value);
+        }
+        if trailers.is_empty() {
+            Ok(Async::Ready(None))
+        } else {
+            Ok(Async::Ready(Some(trailers)))
+        }
+    }
+
+    #[inline]
+    fn is_end_stream(&self) -> bool {
+        Body::is_end_stream(self)
+    }
 }
 
 impl<'a> From<Form<'a>> for Body<'a> {
@@ -72,9 +441,534 @@ impl<'a> From<Form<'a>> for Body<'a> {
     ///
     #[inline]
     fn from(form: Form<'a>) -> Self {
+        BodyBuilder::default().build(form)
+    }
+}
+
+/// Configures how a `Form` is turned into a `Body`.
+///
+/// By default every chunk is at most 2048 bytes. Large uploads usually
+/// benefit from a bigger fixed chunk size, or from an adaptive one.
+///
+#[derive(Clone, Debug)]
+pub struct BodyBuilder {
+    chunk_size: ChunkSize,
+    rate_limiter: Option<RateLimiter>,
+    #[cfg(any(
+        feature = "md5",
+        feature = "sha1",
+        feature = "sha256",
+        feature = "crc32c"
+    ))]
+    digest: Option<BodyDigest>,
+}
+
+impl Default for BodyBuilder {
+    #[inline]
+    fn default() -> Self {
         Self {
-            buf_size: 2048,
+            chunk_size: ChunkSize::fixed(DEFAULT_CHUNK_SIZE),
+            rate_limiter: None,
+            #[cfg(any(
+                feature = "md5",
+                feature = "sha1",
+                feature = "sha256",
+                feature = "crc32c"
+            ))]
+            digest: None,
+        }
+    }
+}
+
+impl BodyBuilder {
+    /// Emits chunks of at most `size` bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is zero.
+    ///
+    #[inline]
+    pub fn chunk_size(mut self, size: usize) -> Self {
+        assert!(size > 0, "chunk size must be greater than zero");
+        self.chunk_size = ChunkSize::fixed(size);
+        self
+    }
+
+    /// Emits chunks of at least `min` and at most `max` bytes.
+    ///
+    /// The chunk size starts at `min`, doubles every time the reader fills
+    /// the whole buffer, and halves every time it fills less than half of
+    /// it. Large parts are streamed in big chunks, while small parts do not
+    /// hold on to big buffers.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `min` is zero or greater than `max`.
+    ///
+    #[inline]
+    pub fn adaptive(mut self, min: usize, max: usize) -> Self {
+        assert!(min > 0, "chunk size must be greater than zero");
+        assert!(min <= max, "minimum chunk size must not exceed the maximum");
+        self.chunk_size = ChunkSize {
+            current: min,
+            min,
+            max,
+        };
+        self
+    }
+
+    /// Limits the rate at which the body emits bytes. Each chunk is held
+    /// back until the limiter allows it to be sent, so the chunk size
+    /// should be small compared to the burst.
+    ///
+    #[inline]
+    pub fn rate_limiter(mut self, limiter: RateLimiter) -> Self {
+        self.rate_limiter = Some(limiter);
+        self
+    }
+
+    /// Computes a digest of the emitted bytes while they are emitted. It
+    /// is available from [`Body::digest`](struct.Body.html#method.digest)
+    /// once the body is finished, and sent as a `Content-Digest` trailer
+    /// by hyper.
+    ///
+    /// To send the digest as a header instead, compute it up front with
+    /// [`Form::digest`](struct.Form.html#method.digest).
+    ///
+    #[cfg(any(
+        feature = "md5",
+        feature = "sha1",
+        feature = "sha256",
+        feature = "crc32c"
+    ))]
+    #[inline]
+    pub fn digest(mut self, algorithm: Algorithm) -> Self {
+        self.digest = Some(BodyDigest::Algorithm(algorithm));
+        self
+    }
+
+    /// Computes an HMAC-SHA256 of the emitted bytes while they are
+    /// emitted. It is available from
+    /// [`Body::digest`](struct.Body.html#method.digest) once the body is
+    /// finished.
+    ///
+    #[cfg(feature = "hmac")]
+    #[inline]
+    pub fn hmac_sha256(mut self, key: &[u8]) -> Self {
+        self.digest = Some(BodyDigest::HmacSha256(key.to_vec()));
+        self
+    }
+
+    /// Turns a `Form` into a `Body` with this configuration.
+    ///
+    #[inline]
+    pub fn build(self, form: Form<'_>) -> Body<'_> {
+        let content_length = form.content_length();
+        Body {
+            buf: BytesMut::with_capacity(self.chunk_size.current),
+            chunk_size: self.chunk_size,
+            content_length,
+            remaining: content_length,
+            finished: false,
+            rate_limiter: self.rate_limiter,
+            pending: None,
+            #[cfg(any(
+                feature = "md5",
+                feature = "sha1",
+                feature = "sha256",
+                feature = "crc32c"
+            ))]
+            checksums: form.checksums(),
+            #[cfg(any(
+                feature = "md5",
+                feature = "sha1",
+                feature = "sha256",
+                feature = "crc32c"
+            ))]
+            hasher: self.digest.as_ref().map(BodyDigest::hasher),
+            #[cfg(any(
+                feature = "md5",
+                feature = "sha1",
+                feature = "sha256",
+                feature = "crc32c"
+            ))]
+            digest_algorithm: self.digest.as_ref().and_then(BodyDigest::algorithm),
+            #[cfg(any(
+                feature = "md5",
+                feature = "sha1",
+                feature = "sha256",
+                feature = "crc32c"
+            ))]
+            digest: None,
+            #[cfg(any(
+                feature = "md5",
+                feature = "sha1",
+                feature = "sha256",
+                feature = "crc32c"
+            ))]
+            trailers_pending: cfg!(feature = "hyper")
+                && self
+                    .digest
+                    .as_ref()
+                    .and_then(BodyDigest::algorithm)
+                    .is_some(),
             reader: Box::new(form.into_reader()),
+            pipe: ChunkPipe::default(),
+        }
+    }
+}
+
+/// The chunk size of a `Body`, which is fixed when `min` equals `max`.
+///
+#[derive(Clone, Debug)]
+struct ChunkSize {
+    current: usize,
+    min: usize,
+    max: usize,
+}
+
+impl ChunkSize {
+    #[inline]
+    fn fixed(size: usize) -> Self {
+        Self {
+            current: size,
+            min: size,
+            max: size,
+        }
+    }
+
+    /// Grows or shrinks the chunk size depending on how much of the
+    /// buffer the last read filled.
+    ///
+    #[inline]
+    fn adapt(&mut self, read: usize) {
+        if read == self.current {
+            self.current = self.current.saturating_mul(2).min(self.max);
+        } else if read < self.current / 2 {
+            self.current = (self.current / 2).max(self.min);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Body;
+    use crate::throttle::{tests::MockClock, Clock};
+    use crate::{BoundaryGenerator, Error, Form, RateLimiter};
+    use bytes::Bytes;
+    use futures::Stream;
+    use std::io::{self, Cursor, Read};
+
+    struct TestGenerator;
+
+    impl BoundaryGenerator for TestGenerator {
+        fn generate_boundary() -> String {
+            "boundary".to_string()
+        }
+    }
+
+    fn form() -> Form<'static> {
+        let mut form = Form::new::<TestGenerator>();
+        form.add_reader2(
+            "data",
+            Cursor::new(vec![b'x'; 10_000]),
+            None::<&str>,
+            None,
+            Some(10_000),
+        );
+        form
+    }
+
+    fn expected() -> Vec<u8> {
+        let mut expected = Vec::new();
+        form().into_reader().read_to_end(&mut expected).unwrap();
+        expected
+    }
+
+    fn concat(chunks: &[Bytes]) -> Vec<u8> {
+        chunks.iter().flat_map(|chunk| chunk.iter().cloned()).collect()
+    }
+
+    #[test]
+    fn test_fixed_chunk_size() {
+        let chunks: Vec<_> = Body::builder()
+            .chunk_size(100)
+            .build(form())
+            .wait()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert!(chunks.iter().all(|chunk| chunk.len() <= 100));
+        assert_eq!(expected(), concat(&chunks));
+    }
+
+    #[test]
+    fn test_adaptive_chunk_size() {
+        let chunks: Vec<_> = Body::builder()
+            .adaptive(16, 1024)
+            .build(form())
+            .wait()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let max = chunks.iter().map(|chunk| chunk.len()).max().unwrap();
+        assert_eq!(1024, max);
+        assert_eq!(expected(), concat(&chunks));
+    }
+
+    #[test]
+    fn test_remaining() {
+        let mut form = form();
+        form.add_text("text", "Hello World!");
+        let content_length = form.content_length();
+        let mut body = Body::builder().chunk_size(100).build(form);
+        assert_eq!(content_length, body.content_length());
+        assert_eq!(content_length, body.remaining());
+        let first = body.poll().unwrap();
+        let emitted = match first {
+            futures::Async::Ready(Some(chunk)) => chunk.len() as u64,
+            _ => panic!("expected a chunk"),
+        };
+        assert_eq!(content_length.map(|len| len - emitted), body.remaining());
+        assert!(!body.is_end_stream());
+        let rest: Vec<_> = body.by_ref().wait().collect::<Result<_, _>>().unwrap();
+        assert!(!rest.is_empty());
+        assert_eq!(Some(0), body.remaining());
+        assert!(body.is_end_stream());
+        assert_eq!((0, Some(0)), body.size_hint());
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let clock = MockClock::new();
+        let start = clock.now();
+        let limiter = RateLimiter::with_clock(1000, 1000, clock.clone());
+        let body = Body::builder()
+            .chunk_size(100)
+            .rate_limiter(limiter)
+            .build(form());
+        let chunks: Vec<_> = body.wait().collect::<Result<_, _>>().unwrap();
+        assert_eq!(expected(), concat(&chunks));
+        let elapsed = clock.now() - start;
+        let expected = (expected().len() - 1000) as f64 / 1000.0;
+        assert!((elapsed.as_secs_f64() - expected).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_unknown_length() {
+        let mut form = Form::default();
+        form.add_reader("data", Cursor::new("unknown"));
+        let body = Body::from(form);
+        assert_eq!(None, body.content_length());
+        assert_eq!((0, None), body.size_hint());
+    }
+
+    #[test]
+    fn test_empty_form() {
+        let chunks: Vec<_> = Body::from(Form::new::<TestGenerator>())
+            .wait()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(b"--boundary--\r\n".to_vec(), concat(&chunks));
+    }
+
+    #[test]
+    fn test_failing_reader() {
+        let mut form = Form::new::<TestGenerator>();
+        form.add_text("text", "hello");
+        form.add_reader_factory(
+            "lazy",
+            || -> io::Result<Box<dyn Read + Send>> {
+                Err(io::Error::new(io::ErrorKind::NotFound, "gone"))
+            },
+            None::<&str>,
+            None,
+            None,
+        );
+        let err = Body::from(form)
+            .wait()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap_err();
+        match Error::from(err) {
+            Error::Read { index: 1, .. } => (),
+            err => panic!("unexpected error: {:?}", err),
+        }
+    }
+
+    #[test]
+    fn test_iter() {
+        let chunks = vec![Bytes::from(vec![b'a'; 100]), Bytes::from(vec![b'b'; 100])];
+        let iter_form = |length| {
+            let mut form = Form::new::<TestGenerator>();
+            form.add_iter("data", chunks.clone(), Some(length));
+            form
+        };
+        let emitted: Vec<_> = Body::builder()
+            .chunk_size(1024)
+            .build(iter_form(200))
+            .wait()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        for chunk in &chunks {
+            assert!(emitted
+                .iter()
+                .any(|emitted| emitted.as_ptr() == chunk.as_ptr()));
+        }
+        let mut expected = Vec::new();
+        iter_form(200)
+            .into_reader()
+            .read_to_end(&mut expected)
+            .unwrap();
+        assert_eq!(expected, concat(&emitted));
+
+        let err = Body::from(iter_form(150))
+            .wait()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap_err();
+        match Error::from(err) {
+            Error::LengthMismatch { expected: 150, .. } => (),
+            err => panic!("unexpected error: {:?}", err),
+        }
+    }
+
+    #[cfg(any(feature = "md5", feature = "gzip"))]
+    #[test]
+    fn test_iter_without_pipe() {
+        let chunks = vec![Bytes::from(vec![b'a'; 100]), Bytes::from(vec![b'b'; 100])];
+        let check = |configure: &dyn Fn(&mut Form)| {
+            let iter_form = || {
+                let mut form = Form::new::<TestGenerator>();
+                form.add_iter("data", chunks.clone(), None);
+                configure(&mut form);
+                form
+            };
+            let mut body = Body::builder().chunk_size(1024).build(iter_form());
+            let emitted: Vec<_> = body.by_ref().wait().collect::<Result<_, _>>().unwrap();
+            for chunk in &chunks {
+                assert!(emitted
+                    .iter()
+                    .all(|emitted| emitted.as_ptr() != chunk.as_ptr()));
+            }
+            let mut expected = Vec::new();
+            iter_form()
+                .into_reader()
+                .read_to_end(&mut expected)
+                .unwrap();
+            assert_eq!(expected, concat(&emitted));
+            body
+        };
+
+        #[cfg(feature = "md5")]
+        {
+            let body = check(&|form| form.set_checksum(0, crate::Algorithm::Md5).unwrap());
+            let data: Vec<_> = chunks.iter().flat_map(|chunk| chunk.to_vec()).collect();
+            let expected = crate::checksum::Hasher::new(crate::Algorithm::Md5)
+                .consume(&data[..])
+                .unwrap();
+            assert_eq!(expected, body.checksums()[0].value);
+        }
+        #[cfg(feature = "gzip")]
+        check(&|form| form.set_content_encoding(0, crate::Encoding::Gzip).unwrap());
+    }
+
+    #[cfg(feature = "md5")]
+    #[test]
+    fn test_streaming_checksum() {
+        let mut form = Form::default();
+        form.add_reader("data", Cursor::new("hello world"));
+        form.add_text("text", "hello world");
+        form.set_checksum(0, crate::Algorithm::Md5).unwrap();
+        form.set_checksum(1, crate::Algorithm::Md5).unwrap();
+        let mut body = Body::from(form);
+        assert!(body.checksums().is_empty());
+        let chunks: Vec<_> = body.by_ref().wait().collect::<Result<_, _>>().unwrap();
+        let body_string = String::from_utf8(concat(&chunks)).unwrap();
+        assert_eq!(1, body_string.matches("content-md5: ").count());
+        let checksums = body.checksums();
+        assert_eq!(2, checksums.len());
+        assert_eq!(("data", 0), (&checksums[0].name[..], checksums[0].index));
+        assert_eq!(checksums[0].value, checksums[1].value);
+        assert_eq!("XrY7u+Ae7tCTyyK7j1rNww==", checksums[0].to_base64());
+    }
+
+    fn replayable_form() -> Form<'static> {
+        let mut form = Form::new::<TestGenerator>();
+        form.add_bytes("data", vec![b'x'; 10_000]);
+        form
+    }
+
+    #[test]
+    fn test_rewind() {
+        let form = replayable_form();
+        let first: Vec<_> = form
+            .rewind()
+            .unwrap()
+            .wait()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let second: Vec<_> = form
+            .rewind()
+            .unwrap()
+            .wait()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(concat(&first), concat(&second));
+        assert!(super::Body::from(form).content_length().is_some());
+    }
+
+    #[cfg(feature = "sha256")]
+    #[test]
+    fn test_digest() {
+        let form = replayable_form();
+        let expected = form.content_digest(crate::Algorithm::Sha256).unwrap();
+        let mut body = Body::builder().digest(crate::Algorithm::Sha256).build(form);
+        assert_eq!(None, body.digest());
+        body.by_ref().wait().for_each(|chunk| {
+            chunk.unwrap();
+        });
+        assert_eq!(Some(expected), body.content_digest());
+    }
+
+    #[cfg(all(feature = "hyper", feature = "sha256"))]
+    #[test]
+    fn test_digest_trailer() {
+        use futures::Async;
+        use hyper::body::Payload;
+
+        let form = replayable_form();
+        let expected = form.content_digest(crate::Algorithm::Sha256).unwrap();
+        let mut body = Body::builder().digest(crate::Algorithm::Sha256).build(form);
+        assert!(body.content_length().is_some());
+        // hyper's HTTP/2 client ends the stream without polling the
+        // trailers as soon as the body reports its end.
+        //
+        loop {
+            match body.poll_data().unwrap() {
+                Async::Ready(Some(_)) => {
+                    assert!(!Payload::is_end_stream(&body));
+                    if body.remaining() == Some(0) {
+                        assert_eq!(Some(&expected), body.content_digest().as_ref());
+                    }
+                }
+                Async::Ready(None) => break,
+                Async::NotReady => panic!("the body is not throttled"),
+            }
+        }
+        match body.poll_trailers().unwrap() {
+            Async::Ready(Some(trailers)) => assert_eq!(expected, trailers["content-digest"]),
+            trailers => panic!("unexpected trailers: {:?}", trailers),
+        }
+        assert!(Payload::is_end_stream(&body));
+    }
+
+    #[cfg(feature = "hmac")]
+    #[test]
+    fn test_hmac_sha256() {
+        let form = replayable_form();
+        let expected = form.hmac_sha256(b"secret").unwrap();
+        let mut body = Body::builder().hmac_sha256(b"secret").build(form);
+        body.by_ref().wait().for_each(|chunk| {
+            chunk.unwrap();
+        });
+        assert_eq!(Some(&expected[..]), body.digest());
+        assert_eq!(None, body.content_digest());
+    }
+}
@@ -8,6 +8,7 @@
 //
 
 use rand::{distributions::Alphanumeric, rngs::SmallRng, FromEntropy, Rng};
+use std::fmt::Write;
 use std::iter::FromIterator;
 
 /// A `BoundaryGenerator` is a policy to generate a random string to use
@@ -48,3 +49,53 @@ impl BoundaryGenerator for RandomAsciiGenerator {
         String::from_iter(ascii.take(6))
     }
 }
+
+/// Creates boundaries like Chrome and Safari, such as
+/// `----WebKitFormBoundary7MA4YWxkTrZu0gW`.
+///
+pub struct WebKitGenerator;
+
+impl BoundaryGenerator for WebKitGenerator {
+    fn generate_boundary() -> String {
+        let mut rng = SmallRng::from_entropy();
+        let ascii = rng.sample_iter(&Alphanumeric);
+
+        format!(
+            "----WebKitFormBoundary{}",
+            String::from_iter(ascii.take(16))
+        )
+    }
+}
+
+/// Creates boundaries like Firefox, such as
+/// `----geckoformboundary` followed by 32 hexadecimal digits.
+///
+pub struct GeckoGenerator;
+
+impl BoundaryGenerator for GeckoGenerator {
+    fn generate_boundary() -> String {
+        hex_boundary("----geckoformboundary", 16)
+    }
+}
+
+/// Creates boundaries like curl, such as 24 dashes followed by 16
+/// hexadecimal digits.
+///
+pub struct CurlGenerator;
+
+impl BoundaryGenerator for CurlGenerator {
+    fn generate_boundary() -> String {
+        hex_boundary("------------------------", 8)
+    }
+}
+
+/// Appends `bytes` random bytes in hexadecimal to `prefix`.
+///
+fn hex_boundary(prefix: &str, bytes: usize) -> String {
+    let mut rng = SmallRng::from_entropy();
+    let mut boundary = String::from(prefix);
+    for _ in 0..bytes {
+        let _ = write!(boundary, "{:02x}", rng.gen::<u8>());
+    }
+    boundary
+}
@@ -0,0 +1,369 @@
+// Copyright 2018 rust-multipart-rfc7578 Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+//
+
+use std::{
+    fmt,
+    io::{self, Read},
+    sync::{Arc, Mutex},
+};
+
+/// A checksum algorithm. Each algorithm is enabled by the feature of the
+/// same name.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Algorithm {
+    /// MD5, sent as a `Content-MD5` header.
+    ///
+    #[cfg(feature = "md5")]
+    Md5,
+
+    /// SHA-1, sent as a `Content-Digest` header.
+    ///
+    #[cfg(feature = "sha1")]
+    Sha1,
+
+    /// SHA-256, sent as a `Content-Digest` header.
+    ///
+    #[cfg(feature = "sha256")]
+    Sha256,
+
+    /// CRC32C, big-endian, sent as a `Content-Digest` header.
+    ///
+    #[cfg(feature = "crc32c")]
+    Crc32c,
+}
+
+impl Algorithm {
+    /// Returns the key of the algorithm in the HTTP digest algorithm
+    /// registry.
+    ///
+    /// [See](https://www.rfc-editor.org/rfc/rfc9530#section-5).
+    ///
+    pub fn key(self) -> &'static str {
+        match self {
+            #[cfg(feature = "md5")]
+            Algorithm::Md5 => "md5",
+            #[cfg(feature = "sha1")]
+            Algorithm::Sha1 => "sha",
+            #[cfg(feature = "sha256")]
+            Algorithm::Sha256 => "sha-256",
+            #[cfg(feature = "crc32c")]
+            Algorithm::Crc32c => "crc32c",
+        }
+    }
+}
+
+/// The checksum of the data of a part.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Checksum {
+    /// The index of the part in the form.
+    ///
+    pub index: usize,
+
+    /// The name of the part.
+    ///
+    pub name: String,
+
+    /// The algorithm.
+    ///
+    pub algorithm: Algorithm,
+
+    /// The raw digest.
+    ///
+    pub value: Vec<u8>,
+}
+
+impl Checksum {
+    /// Returns the digest encoded as base64.
+    ///
+    #[inline]
+    pub fn to_base64(&self) -> String {
+        base64::encode(&self.value)
+    }
+
+    /// Returns the name and value of the header carrying the checksum.
+    ///
+    /// MD5 uses `Content-MD5`, as described by
+    /// [RFC 1864](https://tools.ietf.org/html/rfc1864). The other
+    /// algorithms use `Content-Digest`, as described by
+    /// [RFC 9530](https://www.rfc-editor.org/rfc/rfc9530).
+    ///
+    pub fn header(&self) -> (&'static str, String) {
+        header(self.algorithm, &self.value)
+    }
+}
+
+/// Returns the name and value of the header carrying a checksum.
+///
+pub(crate) fn header(algorithm: Algorithm, value: &[u8]) -> (&'static str, String) {
+    match algorithm {
+        #[cfg(feature = "md5")]
+        Algorithm::Md5 => ("content-md5", base64::encode(value)),
+        #[allow(unreachable_patterns)]
+        _ => ("content-digest", content_digest(algorithm, value)),
+    }
+}
+
+/// Returns the value of a `Content-Digest` header carrying a digest.
+///
+/// [See](https://www.rfc-editor.org/rfc/rfc9530#section-2).
+///
+pub(crate) fn content_digest(algorithm: Algorithm, value: &[u8]) -> String {
+    format!("{}=:{}:", algorithm.key(), base64::encode(value))
+}
+
+/// Encodes a digest as lowercase hexadecimal.
+///
+#[cfg(feature = "hmac")]
+pub(crate) fn to_hex(value: &[u8]) -> String {
+    value.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Computes a checksum incrementally.
+///
+pub(crate) struct Hasher(State);
+
+enum State {
+    #[cfg(feature = "md5")]
+    Md5(md5::Md5),
+    #[cfg(feature = "sha1")]
+    Sha1(sha1::Sha1),
+    #[cfg(feature = "sha256")]
+    Sha256(sha2::Sha256),
+    #[cfg(feature = "crc32c")]
+    Crc32c(u32),
+    #[cfg(feature = "hmac")]
+    HmacSha256(Box<hmac::Hmac<sha2::Sha256>>),
+}
+
+impl Hasher {
+    pub(crate) fn new(algorithm: Algorithm) -> Self {
+        Hasher(match algorithm {
+            #[cfg(feature = "md5")]
+            Algorithm::Md5 => State::Md5(md5::Digest::new()),
+            #[cfg(feature = "sha1")]
+            Algorithm::Sha1 => State::Sha1(sha1::Digest::new()),
+            #[cfg(feature = "sha256")]
+            Algorithm::Sha256 => State::Sha256(sha2::Digest::new()),
+            #[cfg(feature = "crc32c")]
+            Algorithm::Crc32c => State::Crc32c(0),
+        })
+    }
+
+    /// Creates a hasher computing an HMAC-SHA256 with `key`.
+    ///
+    #[cfg(feature = "hmac")]
+    pub(crate) fn hmac_sha256(key: &[u8]) -> Self {
+        let mac = hmac::Mac::new_varkey(key).expect("HMAC accepts keys of any length");
+        Hasher(State::HmacSha256(Box::new(mac)))
+    }
+
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        match self.0 {
+            #[cfg(feature = "md5")]
+            State::Md5(ref mut hasher) => md5::Digest::input(hasher, data),
+            #[cfg(feature = "sha1")]
+            State::Sha1(ref mut hasher) => sha1::Digest::input(hasher, data),
+            #[cfg(feature = "sha256")]
+            State::Sha256(ref mut hasher) => sha2::Digest::input(hasher, data),
+            #[cfg(feature = "crc32c")]
+            State::Crc32c(ref mut crc) => *crc = crc32c::crc32c_append(*crc, data),
+            #[cfg(feature = "hmac")]
+            State::HmacSha256(ref mut mac) => hmac::Mac::input(&mut **mac, data),
+        }
+    }
+
+    pub(crate) fn finish(self) -> Vec<u8> {
+        match self.0 {
+            #[cfg(feature = "md5")]
+            State::Md5(hasher) => md5::Digest::result(hasher).to_vec(),
+            #[cfg(feature = "sha1")]
+            State::Sha1(hasher) => sha1::Digest::result(hasher).to_vec(),
+            #[cfg(feature = "sha256")]
+            State::Sha256(hasher) => sha2::Digest::result(hasher).to_vec(),
+            #[cfg(feature = "crc32c")]
+            State::Crc32c(crc) => crc.to_be_bytes().to_vec(),
+            #[cfg(feature = "hmac")]
+            State::HmacSha256(mac) => hmac::Mac::result(*mac).code().to_vec(),
+        }
+    }
+
+    /// Computes the checksum of everything a reader produces.
+    ///
+    pub(crate) fn consume<R: Read>(mut self, mut read: R) -> io::Result<Vec<u8>> {
+        let mut buf = [0; 8 * 1024];
+        loop {
+            match read.read(&mut buf) {
+                Ok(0) => return Ok(self.finish()),
+                Ok(n) => self.update(&buf[..n]),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// What a `Body` computes the digest of its bytes with.
+///
+#[derive(Clone)]
+pub(crate) enum BodyDigest {
+    Algorithm(Algorithm),
+    #[cfg(feature = "hmac")]
+    HmacSha256(Vec<u8>),
+}
+
+impl BodyDigest {
+    #[inline]
+    pub(crate) fn hasher(&self) -> Hasher {
+        match *self {
+            BodyDigest::Algorithm(algorithm) => Hasher::new(algorithm),
+            #[cfg(feature = "hmac")]
+            BodyDigest::HmacSha256(ref key) => Hasher::hmac_sha256(key),
+        }
+    }
+
+    /// Returns the algorithm, unless the digest is keyed.
+    ///
+    #[inline]
+    pub(crate) fn algorithm(&self) -> Option<Algorithm> {
+        match *self {
+            BodyDigest::Algorithm(algorithm) => Some(algorithm),
+            #[cfg(feature = "hmac")]
+            BodyDigest::HmacSha256(_) => None,
+        }
+    }
+}
+
+impl fmt::Debug for BodyDigest {
+    /// Keeps the HMAC key out of logs.
+    ///
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BodyDigest::Algorithm(algorithm) => write!(f, "{:?}", algorithm),
+            #[cfg(feature = "hmac")]
+            BodyDigest::HmacSha256(_) => f.write_str("HmacSha256"),
+        }
+    }
+}
+
+/// How the checksum of a part is computed.
+///
+#[derive(Clone)]
+pub(crate) enum PartChecksum {
+    /// The data was hashed up front, and the checksum is sent as a header.
+    ///
+    Precomputed(Algorithm, Vec<u8>),
+
+    /// The data is hashed while it is streamed.
+    ///
+    Streaming(Algorithm),
+}
+
+/// The checksums computed while a form is written out.
+///
+pub(crate) type Checksums = Arc<Mutex<Vec<Checksum>>>;
+
+/// A reader hashing the data it reads, and recording the checksum once
+/// the inner reader is exhausted.
+///
+pub(crate) struct HashingReader<R> {
+    inner: R,
+    hasher: Option<Hasher>,
+    index: usize,
+    name: String,
+    algorithm: Algorithm,
+    checksums: Checksums,
+}
+
+impl<R: Read> HashingReader<R> {
+    pub(crate) fn new(
+        inner: R,
+        algorithm: Algorithm,
+        index: usize,
+        name: String,
+        checksums: Checksums,
+    ) -> Self {
+        Self {
+            inner,
+            hasher: Some(Hasher::new(algorithm)),
+            index,
+            name,
+            algorithm,
+            checksums,
+        }
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n == 0 {
+            if let Some(hasher) = self.hasher.take() {
+                let checksum = Checksum {
+                    index: self.index,
+                    name: self.name.clone(),
+                    algorithm: self.algorithm,
+                    value: hasher.finish(),
+                };
+                match self.checksums.lock() {
+                    Ok(mut checksums) => checksums.push(checksum),
+                    Err(poisoned) => poisoned.into_inner().push(checksum),
+                }
+            }
+        } else if let Some(ref mut hasher) = self.hasher {
+            hasher.update(&buf[..n]);
+        }
+        Ok(n)
+    }
+}
+
+#[cfg(all(test, feature = "md5", feature = "sha256", feature = "crc32c"))]
+mod tests {
+    use super::{header, Algorithm, Hasher};
+
+    #[test]
+    fn test_headers() {
+        let md5 = Hasher::new(Algorithm::Md5)
+            .consume(&b"hello world"[..])
+            .unwrap();
+        assert_eq!(
+            ("content-md5", "XrY7u+Ae7tCTyyK7j1rNww==".to_string()),
+            header(Algorithm::Md5, &md5)
+        );
+        let sha256 = Hasher::new(Algorithm::Sha256)
+            .consume(&b"hello world"[..])
+            .unwrap();
+        assert_eq!(
+            (
+                "content-digest",
+                "sha-256=:uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek=:".to_string()
+            ),
+            header(Algorithm::Sha256, &sha256)
+        );
+        let crc32c = Hasher::new(Algorithm::Crc32c)
+            .consume(&b"123456789"[..])
+            .unwrap();
+        assert_eq!(vec![0xe3, 0x06, 0x92, 0x83], crc32c);
+    }
+
+    #[cfg(feature = "hmac")]
+    #[test]
+    fn test_hmac_sha256() {
+        // RFC 4231, test case 2.
+        //
+        let mac = Hasher::hmac_sha256(b"Jefe")
+            .consume(&b"what do ya want for nothing?"[..])
+            .unwrap();
+        assert_eq!(
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+            super::to_hex(&mac)
+        );
+    }
+}
@@ -0,0 +1,282 @@
+// Copyright 2018 rust-multipart-rfc7578 Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+//
+
+use crate::source::PartSource;
+use std::{
+    cell::RefCell,
+    cmp,
+    io::{self, Read},
+    sync::{Arc, Mutex},
+};
+
+/// The data of a chunk. With the `bytes` feature, chunks are kept as
+/// `Bytes`, so they can be emitted by a `Body` without being copied.
+///
+#[cfg(feature = "bytes")]
+pub(crate) type Chunk = bytes::Bytes;
+
+#[cfg(not(feature = "bytes"))]
+pub(crate) type Chunk = Vec<u8>;
+
+/// A type that can be produced by the iterator of a part added with
+/// [`Form::add_iter`](struct.Form.html#method.add_iter).
+///
+/// It is implemented for `Vec<u8>`, `String`, `Bytes` with the `bytes`
+/// feature, and results of any of these, whose errors are reported when
+/// the part is read.
+///
+pub trait IntoChunk {
+    #[doc(hidden)]
+    fn into_chunk(self) -> io::Result<Chunk>;
+}
+
+impl IntoChunk for Vec<u8> {
+    #[cfg_attr(not(feature = "bytes"), allow(clippy::useless_conversion))]
+    #[inline]
+    fn into_chunk(self) -> io::Result<Chunk> {
+        Ok(self.into())
+    }
+}
+
+impl IntoChunk for String {
+    #[inline]
+    fn into_chunk(self) -> io::Result<Chunk> {
+        Ok(self.into())
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl IntoChunk for bytes::Bytes {
+    #[inline]
+    fn into_chunk(self) -> io::Result<Chunk> {
+        Ok(self)
+    }
+}
+
+impl<T: IntoChunk> IntoChunk for io::Result<T> {
+    #[inline]
+    fn into_chunk(self) -> io::Result<Chunk> {
+        self.and_then(IntoChunk::into_chunk)
+    }
+}
+
+/// An iterator of chunks, boxed so parts have a single type.
+///
+pub(crate) type Chunks<'a> = Box<dyn 'a + Iterator<Item = io::Result<Chunk>> + Send>;
+
+/// Hands chunks from the reader of a part straight to a `Body`.
+///
+/// While a body reads the form, its pipe is set for the thread with
+/// [`with_pipe`](fn.with_pipe.html). Instead of copying a chunk into the
+/// buffer it is given, a chunk reader then puts the chunk into the pipe
+/// and returns its length, and the body emits the chunk in place of its
+/// buffer. The readers in between only count the bytes, so parts whose
+/// data is hashed or compressed while it is streamed are read through
+/// [`Unpiped`](struct.Unpiped.html).
+///
+#[cfg_attr(not(feature = "futures"), allow(dead_code))]
+#[derive(Clone, Default)]
+pub(crate) struct ChunkPipe(Arc<Mutex<Option<Chunk>>>);
+
+impl ChunkPipe {
+    #[cfg(feature = "bytes")]
+    #[inline]
+    fn put(&self, chunk: Chunk) {
+        match self.0.lock() {
+            Ok(mut slot) => *slot = Some(chunk),
+            Err(poisoned) => *poisoned.into_inner() = Some(chunk),
+        }
+    }
+
+    /// Takes the chunk read last, if it went through the pipe.
+    ///
+    #[cfg(feature = "futures")]
+    #[inline]
+    pub(crate) fn take(&self) -> Option<Chunk> {
+        match self.0.lock() {
+            Ok(mut slot) => slot.take(),
+            Err(poisoned) => poisoned.into_inner().take(),
+        }
+    }
+}
+
+thread_local! {
+    /// The pipe of the `Body` reading on this thread, if any.
+    ///
+    static PIPE: RefCell<Option<ChunkPipe>> = const { RefCell::new(None) };
+}
+
+/// Calls `f` with `pipe` set as the pipe of the thread, and restores the
+/// previous pipe afterwards, even if `f` panics.
+///
+pub(crate) fn with_pipe<T, F>(pipe: Option<ChunkPipe>, f: F) -> T
+where
+    F: FnOnce() -> T,
+{
+    struct Restore(Option<ChunkPipe>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let previous = self.0.take();
+            PIPE.with(|slot| *slot.borrow_mut() = previous);
+        }
+    }
+
+    let _restore = Restore(PIPE.with(|slot| slot.replace(pipe)));
+    f()
+}
+
+/// A reader whose chunk readers copy their chunks, for readers that look
+/// at the data.
+///
+pub(crate) struct Unpiped<R>(pub(crate) R);
+
+impl<R: Read> Read for Unpiped<R> {
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        with_pipe(None, || self.0.read(buf))
+    }
+}
+
+/// A reader over the chunks of an iterator.
+///
+pub(crate) struct ChunkReader<'a> {
+    chunks: Chunks<'a>,
+    current: Chunk,
+    position: usize,
+}
+
+impl<'a> ChunkReader<'a> {
+    #[inline]
+    pub(crate) fn new(chunks: Chunks<'a>) -> Self {
+        Self {
+            chunks,
+            current: Chunk::default(),
+            position: 0,
+        }
+    }
+
+    /// Puts the bytes up to `end` into the pipe of the thread, if it has
+    /// one.
+    ///
+    #[cfg(feature = "bytes")]
+    #[inline]
+    fn pipe(&self, end: usize) -> bool {
+        PIPE.with(|slot| match *slot.borrow() {
+            Some(ref pipe) => {
+                pipe.put(self.current.slice(self.position, end));
+                true
+            }
+            None => false,
+        })
+    }
+
+    #[cfg(not(feature = "bytes"))]
+    #[inline]
+    fn pipe(&self, _end: usize) -> bool {
+        false
+    }
+}
+
+impl<'a> Read for ChunkReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.current.len() {
+            match self.chunks.next() {
+                Some(chunk) => {
+                    self.current = chunk?;
+                    self.position = 0;
+                }
+                None => return Ok(0),
+            }
+        }
+        let end = cmp::min(self.current.len(), self.position + buf.len());
+        let num = end - self.position;
+        if buf.is_empty() || !self.pipe(end) {
+            buf[..num].copy_from_slice(&self.current[self.position..end]);
+        }
+        self.position = end;
+        Ok(num)
+    }
+}
+
+/// The source of a part produced by an iterator, which can only be read
+/// once.
+///
+pub(crate) struct ChunkSource<'a> {
+    chunks: Chunks<'a>,
+    length: Option<u64>,
+}
+
+impl<'a> ChunkSource<'a> {
+    #[inline]
+    pub(crate) fn new(chunks: Chunks<'a>, length: Option<u64>) -> Self {
+        Self { chunks, length }
+    }
+}
+
+impl<'a> PartSource<'a> for ChunkSource<'a> {
+    #[inline]
+    fn length(&self) -> Option<u64> {
+        self.length
+    }
+
+    #[inline]
+    fn into_reader(self: Box<Self>) -> io::Result<Box<dyn 'a + Read + Send>> {
+        Ok(Box::new(ChunkReader::new(self.chunks)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChunkReader, IntoChunk};
+    use std::io::{self, Read};
+
+    #[test]
+    fn test_read() {
+        let chunks = vec![Ok(b"ab".to_vec()), Ok(vec![]), Ok(b"cde".to_vec())];
+        let chunks = Box::new(chunks.into_iter().map(IntoChunk::into_chunk));
+        let mut reader = ChunkReader::new(chunks);
+        let mut buf = [0; 2];
+        assert_eq!(2, reader.read(&mut buf).unwrap());
+        assert_eq!(b"ab", &buf);
+        assert_eq!(2, reader.read(&mut buf).unwrap());
+        assert_eq!(b"cd", &buf);
+        assert_eq!(1, reader.read(&mut buf).unwrap());
+        assert_eq!(0, reader.read(&mut buf).unwrap());
+
+        let chunks = vec![Ok("a".to_string()), Err(io::Error::other("failed"))];
+        let chunks = Box::new(chunks.into_iter().map(IntoChunk::into_chunk));
+        let mut out = vec![];
+        let result = ChunkReader::new(chunks).read_to_end(&mut out);
+        assert_eq!("failed", result.unwrap_err().to_string());
+        assert_eq!(b"a", &out[..]);
+    }
+
+    #[cfg(feature = "futures")]
+    #[test]
+    fn test_pipe() {
+        use super::{with_pipe, ChunkPipe, Unpiped};
+
+        let chunk = bytes::Bytes::from(&b"abcdef"[..]);
+        let chunks = Box::new(Some(chunk.clone()).into_iter().map(IntoChunk::into_chunk));
+        let pipe = ChunkPipe::default();
+        let mut reader = Unpiped(ChunkReader::new(chunks));
+        let mut buf = [0; 2];
+        with_pipe(Some(pipe.clone()), || {
+            assert_eq!(2, reader.0.read(&mut buf).unwrap());
+            assert_eq!(&b"ab"[..], &pipe.take().unwrap()[..]);
+            assert_eq!([0; 2], buf);
+            assert_eq!(2, reader.read(&mut buf).unwrap());
+            assert_eq!(None, pipe.take());
+            assert_eq!(b"cd", &buf);
+        });
+        assert_eq!(2, reader.0.read(&mut buf).unwrap());
+        assert_eq!(None, pipe.take());
+        assert_eq!(b"ef", &buf);
+    }
+}
@@ -0,0 +1,356 @@
+// Copyright 2018 rust-multipart-rfc7578 Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+//
+
+use crate::error::Error;
+use crate::part;
+use mime::Mime;
+use std::{path::PathBuf, str::FromStr};
+
+/// Where the data of a field parsed from a curl `-F` spec comes from.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CurlValue {
+    /// A literal value, such as `name=value`.
+    ///
+    Text(String),
+
+    /// A file upload, such as `name=@file`. A path of `-` is stdin.
+    ///
+    File(PathBuf),
+
+    /// The content of a file sent as the value of a field, such as
+    /// `name=<file`. A path of `-` is stdin.
+    ///
+    Content(PathBuf),
+}
+
+/// A header of a field parsed from a curl `-F` spec.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CurlHeader {
+    /// A header written inline, such as `headers="X-Id: 1"`.
+    ///
+    Inline(String),
+
+    /// A file of headers, one per line, such as `headers=@file`. Empty
+    /// lines and lines starting with `#` are skipped.
+    ///
+    File(PathBuf),
+}
+
+/// A field in the syntax of curl's `-F` option.
+///
+/// The value is either literal, a file upload starting with `@`, or the
+/// content of a file starting with `<`. It can be followed by `;type=`,
+/// `;filename=` and `;headers=` modifiers. Values and modifiers can be
+/// double-quoted to include `;` and `,`, with `\"` and `\\` escaping a
+/// quote and a backslash.
+///
+/// [See](https://curl.se/docs/manpage.html#-F).
+///
+/// # Examples
+///
+/// ```
+/// use multipart_rfc7578::{CurlSpec, CurlValue};
+///
+/// let spec: CurlSpec = "file=@\"report;v2.pdf\";type=application/pdf".parse().unwrap();
+///
+/// assert_eq!("file", spec.name());
+/// assert_eq!(&CurlValue::File("report;v2.pdf".into()), spec.value());
+/// ```
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct CurlSpec {
+    pub(crate) name: String,
+    pub(crate) value: CurlValue,
+    pub(crate) content_type: Option<Mime>,
+    pub(crate) filename: Option<String>,
+    pub(crate) headers: Vec<CurlHeader>,
+
+    /// The position of the `headers=` modifier of each header, for
+    /// errors.
+    ///
+    pub(crate) header_positions: Vec<usize>,
+}
+
+impl CurlSpec {
+    /// Returns the name of the field.
+    ///
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns where the data comes from.
+    ///
+    #[inline]
+    pub fn value(&self) -> &CurlValue {
+        &self.value
+    }
+
+    /// Returns the content type set with `;type=`.
+    ///
+    #[inline]
+    pub fn content_type(&self) -> Option<&Mime> {
+        self.content_type.as_ref()
+    }
+
+    /// Returns the filename set with `;filename=`.
+    ///
+    #[inline]
+    pub fn filename(&self) -> Option<&str> {
+        self.filename.as_deref()
+    }
+
+    /// Returns the headers set with `;headers=`.
+    ///
+    #[inline]
+    pub fn headers(&self) -> &[CurlHeader] {
+        &self.headers
+    }
+}
+
+impl FromStr for CurlSpec {
+    type Err = Error;
+
+    fn from_str(spec: &str) -> Result<Self, Error> {
+        Parser { spec, pos: 0 }.parse()
+    }
+}
+
+/// Parses a spec, keeping track of the position for errors.
+///
+struct Parser<'s> {
+    spec: &'s str,
+    pos: usize,
+}
+
+impl<'s> Parser<'s> {
+    fn error(&self, pos: usize, message: &str) -> Error {
+        Error::Spec {
+            spec: self.spec.to_string(),
+            position: pos,
+            message: message.to_string(),
+        }
+    }
+
+    #[inline]
+    fn rest(&self) -> &'s str {
+        &self.spec[self.pos..]
+    }
+
+    fn parse(mut self) -> Result<CurlSpec, Error> {
+        let name = match self.spec.find('=') {
+            Some(0) => return Err(self.error(0, "missing field name")),
+            Some(end) => &self.spec[..end],
+            None => return Err(self.error(self.spec.len(), "expected `=` after the field name")),
+        };
+        self.pos = name.len() + 1;
+
+        let value = if self.rest().starts_with('@') {
+            self.pos += 1;
+            let start = self.pos;
+            let path = self.word(true)?;
+            if self.rest().starts_with(',') {
+                return Err(self.error(
+                    self.pos,
+                    "uploading several files in one field is not supported",
+                ));
+            }
+            if path.is_empty() {
+                return Err(self.error(start, "missing file name after `@`"));
+            }
+            CurlValue::File(path.into())
+        } else if self.rest().starts_with('<') {
+            self.pos += 1;
+            let start = self.pos;
+            let path = self.word(false)?;
+            if path.is_empty() {
+                return Err(self.error(start, "missing file name after `<`"));
+            }
+            CurlValue::Content(path.into())
+        } else {
+            CurlValue::Text(self.word(false)?)
+        };
+
+        let mut spec = CurlSpec {
+            name: name.to_string(),
+            value,
+            content_type: None,
+            filename: None,
+            headers: vec![],
+            header_positions: vec![],
+        };
+        while !self.rest().is_empty() {
+            // Every modifier starts with a `;`, since words stop there.
+            //
+            self.pos += 1;
+            self.pos += self.rest().len() - self.rest().trim_start().len();
+            let start = self.pos;
+            let key = match self.rest().find('=') {
+                Some(end) => &self.rest()[..end],
+                None => {
+                    return Err(self.error(start, "expected `type=`, `filename=` or `headers=`"))
+                }
+            };
+            self.pos += key.len() + 1;
+            match key {
+                "type" => {
+                    let value_start = self.pos;
+                    let value = self.word(false)?;
+                    spec.content_type = Some(
+                        value
+                            .parse()
+                            .map_err(|_| self.error(value_start, "invalid content type"))?,
+                    );
+                }
+                "filename" => spec.filename = Some(self.word(false)?),
+                "headers" => {
+                    let header = if self.rest().starts_with('@') {
+                        self.pos += 1;
+                        CurlHeader::File(self.word(false)?.into())
+                    } else {
+                        let line = self.word(false)?;
+                        if split_header(&line).is_none() {
+                            return Err(self.error(start, &invalid_header(&line)));
+                        }
+                        CurlHeader::Inline(line)
+                    };
+                    spec.headers.push(header);
+                    spec.header_positions.push(start);
+                }
+                _ => return Err(self.error(start, "expected `type=`, `filename=` or `headers=`")),
+            }
+        }
+        Ok(spec)
+    }
+
+    /// Reads a word up to the next `;`, or `,` for file lists. A quoted
+    /// word ends at its closing quote, which must be followed by one of
+    /// those or the end of the spec.
+    ///
+    fn word(&mut self, file_list: bool) -> Result<String, Error> {
+        let is_end = |c: char| c == ';' || (file_list && c == ',');
+        if !self.rest().starts_with('"') {
+            let end = self
+                .rest()
+                .find(is_end)
+                .unwrap_or_else(|| self.rest().len());
+            let word = self.rest()[..end].to_string();
+            self.pos += end;
+            return Ok(word);
+        }
+        let start = self.pos;
+        let mut word = String::new();
+        let mut chars = self.rest().char_indices().skip(1);
+        loop {
+            match chars.next() {
+                Some((_, '\\')) => match chars.next() {
+                    Some((_, c @ '"')) | Some((_, c @ '\\')) => word.push(c),
+                    Some((_, c)) => {
+                        word.push('\\');
+                        word.push(c);
+                    }
+                    None => break,
+                },
+                Some((i, '"')) => {
+                    self.pos += i + 1;
+                    return match self.rest().chars().next() {
+                        Some(c) if !is_end(c) => {
+                            Err(self.error(self.pos, "unexpected text after the closing quote"))
+                        }
+                        _ => Ok(word),
+                    };
+                }
+                Some((_, c)) => word.push(c),
+                None => break,
+            }
+        }
+        Err(self.error(start, "unterminated quote"))
+    }
+}
+
+/// Splits a header line into its name and value, unless the name is not
+/// a token or the value contains line breaks.
+///
+pub(crate) fn split_header(line: &str) -> Option<(String, String)> {
+    let colon = line.find(':')?;
+    let name = line[..colon].trim();
+    let value = line[colon + 1..].trim();
+    if !part::is_valid_header(name, value) {
+        return None;
+    }
+    Some((name.to_string(), value.to_string()))
+}
+
+/// Returns the message of the error reporting an invalid header line.
+///
+pub(crate) fn invalid_header(line: &str) -> String {
+    format!("invalid header `{}`", line.escape_debug())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CurlHeader, CurlSpec, CurlValue};
+    use crate::Error;
+
+    fn parse(spec: &str) -> CurlSpec {
+        spec.parse().unwrap()
+    }
+
+    fn error_position(spec: &str) -> usize {
+        match spec.parse::<CurlSpec>() {
+            Err(Error::Spec { position, .. }) => position,
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_values() {
+        assert_eq!(&CurlValue::Text("a=b".into()), parse("name=a=b").value());
+        assert_eq!(
+            &CurlValue::File("a.txt".into()),
+            parse("name=@a.txt").value()
+        );
+        assert_eq!(
+            &CurlValue::Content("a.txt".into()),
+            parse("name=<a.txt").value()
+        );
+        assert_eq!(&CurlValue::Text("".into()), parse("name=").value());
+    }
+
+    #[test]
+    fn test_modifiers() {
+        let spec = parse(
+            "file=@a.txt; type=text/csv;filename=\"b;\\\"c\\\".csv\";headers=\"X-A: 1\";headers=@h",
+        );
+        assert_eq!(Some(&mime::TEXT_CSV), spec.content_type());
+        assert_eq!(Some("b;\"c\".csv"), spec.filename());
+        assert_eq!(
+            &[
+                CurlHeader::Inline("X-A: 1".into()),
+                CurlHeader::File("h".into())
+            ],
+            spec.headers()
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(5, error_position("value"));
+        assert_eq!(0, error_position("=value"));
+        assert_eq!(5, error_position("name=\"open"));
+        assert_eq!(8, error_position("name=\"a\"b"));
+        assert_eq!(7, error_position("name=@a,b"));
+        assert_eq!(7, error_position("name=a;bogus=1"));
+        assert_eq!(12, error_position("name=a;type=???"));
+        assert_eq!(7, error_position("name=a;headers=\"X-A: 1\r\n\r\n--b\""));
+        assert_eq!(7, error_position("name=a;headers=\"X A: 1\""));
+        assert_eq!(7, error_position("name=a;headers=no colon"));
+    }
+}
@@ -0,0 +1,283 @@
+// Copyright 2018 rust-multipart-rfc7578 Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+//
+
+use crate::error::Error;
+use glob::{MatchOptions, Pattern};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// How the files of a directory are named in their parts.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FilenameStyle {
+    /// The path relative to the parent of the directory, such as
+    /// `photos/2018/a.jpg`, like browsers upload `webkitdirectory`
+    /// selections.
+    ///
+    Relative,
+
+    /// The name of the file only, such as `a.jpg`.
+    ///
+    Basename,
+}
+
+/// Configures [`Form::add_dir`](struct.Form.html#method.add_dir).
+///
+/// By default, directories are walked recursively without following
+/// symlinks, every file is added, and files are named by their relative
+/// path.
+///
+/// Glob patterns containing a `/` are matched against the path relative
+/// to the directory, and other patterns against the name of the file or
+/// directory only. As in a shell, `*` and `?` do not match a `/`, while
+/// `**` matches any number of directories. Includes only apply to files,
+/// while excluding a directory skips everything in it.
+///
+/// # Examples
+///
+/// ```
+/// use multipart_rfc7578::{DirOptions, FilenameStyle};
+///
+/// let options = DirOptions::default()
+///     .include("*.rs")
+///     .unwrap()
+///     .exclude("target")
+///     .unwrap()
+///     .filename_style(FilenameStyle::Basename);
+/// ```
+///
+#[derive(Clone, Debug)]
+pub struct DirOptions {
+    recursive: bool,
+    follow_symlinks: bool,
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+    filename_style: FilenameStyle,
+}
+
+impl Default for DirOptions {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            recursive: true,
+            follow_symlinks: false,
+            include: vec![],
+            exclude: vec![],
+            filename_style: FilenameStyle::Relative,
+        }
+    }
+}
+
+impl DirOptions {
+    /// Sets whether subdirectories are walked.
+    ///
+    #[inline]
+    pub fn recursive(mut self, recursive: bool) -> Self {
+        self.recursive = recursive;
+        self
+    }
+
+    /// Sets whether symlinks are followed. Otherwise, they are skipped.
+    ///
+    #[inline]
+    pub fn follow_symlinks(mut self, follow: bool) -> Self {
+        self.follow_symlinks = follow;
+        self
+    }
+
+    /// Only adds files matching one of the included patterns.
+    ///
+    pub fn include(mut self, pattern: &str) -> Result<Self, Error> {
+        self.include.push(compile(pattern)?);
+        Ok(self)
+    }
+
+    /// Skips files and directories matching the pattern.
+    ///
+    pub fn exclude(mut self, pattern: &str) -> Result<Self, Error> {
+        self.exclude.push(compile(pattern)?);
+        Ok(self)
+    }
+
+    /// Sets how files are named.
+    ///
+    #[inline]
+    pub fn filename_style(mut self, style: FilenameStyle) -> Self {
+        self.filename_style = style;
+        self
+    }
+
+    /// Lists the files to add from a directory, with their filenames, in
+    /// a stable order.
+    ///
+    pub(crate) fn walk(&self, root: &Path) -> Result<Vec<(PathBuf, String)>, Error> {
+        let prefix = root.canonicalize().ok().and_then(|root| {
+            root.file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+        });
+        let mut files = vec![];
+        let mut ancestors = vec![];
+        self.walk_dir(root, "", prefix.as_deref(), &mut ancestors, &mut files)?;
+        Ok(files)
+    }
+
+    fn walk_dir(
+        &self,
+        dir: &Path,
+        relative: &str,
+        prefix: Option<&str>,
+        ancestors: &mut Vec<PathBuf>,
+        files: &mut Vec<(PathBuf, String)>,
+    ) -> Result<(), Error> {
+        let open_error = |source| Error::Open {
+            path: dir.to_path_buf(),
+            source,
+        };
+        // A followed symlink can point back at a directory being walked.
+        //
+        let canonical = dir.canonicalize().map_err(open_error)?;
+        if ancestors.contains(&canonical) {
+            return Ok(());
+        }
+        ancestors.push(canonical);
+
+        let mut entries = fs::read_dir(dir)
+            .map_err(open_error)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(open_error)?;
+        entries.sort();
+        for path in entries {
+            let name = match path.file_name() {
+                Some(name) => name.to_string_lossy().into_owned(),
+                None => continue,
+            };
+            let path_relative = if relative.is_empty() {
+                name.clone()
+            } else {
+                format!("{}/{}", relative, name)
+            };
+            let meta = match fs::symlink_metadata(&path) {
+                Ok(ref meta) if meta.file_type().is_symlink() && !self.follow_symlinks => continue,
+                Ok(ref meta) if meta.file_type().is_symlink() => fs::metadata(&path),
+                meta => meta,
+            }
+            .map_err(|source| Error::Open {
+                path: path.clone(),
+                source,
+            })?;
+            if matches_any(&self.exclude, &name, &path_relative) {
+                continue;
+            }
+            if meta.is_dir() {
+                if self.recursive {
+                    self.walk_dir(&path, &path_relative, prefix, ancestors, files)?;
+                }
+            } else if meta.is_file()
+                && (self.include.is_empty() || matches_any(&self.include, &name, &path_relative))
+            {
+                let filename = match (self.filename_style, prefix) {
+                    (FilenameStyle::Basename, _) => name,
+                    (FilenameStyle::Relative, Some(prefix)) => {
+                        format!("{}/{}", prefix, path_relative)
+                    }
+                    (FilenameStyle::Relative, None) => path_relative,
+                };
+                files.push((path, filename));
+            }
+        }
+        ancestors.pop();
+        Ok(())
+    }
+}
+
+fn compile(pattern: &str) -> Result<Pattern, Error> {
+    Pattern::new(pattern).map_err(|e| Error::Glob {
+        pattern: pattern.to_string(),
+        position: e.pos,
+        message: e.msg.to_string(),
+    })
+}
+
+/// Matches like a shell, where `*` and `?` do not match a `/`.
+///
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+#[inline]
+fn matches_any(patterns: &[Pattern], name: &str, relative: &str) -> bool {
+    patterns.iter().any(|pattern| {
+        if pattern.as_str().contains('/') {
+            pattern.matches_with(relative, MATCH_OPTIONS)
+        } else {
+            pattern.matches_with(name, MATCH_OPTIONS)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compile, matches_any, DirOptions, FilenameStyle};
+    use crate::Error;
+    use std::path::Path;
+
+    fn filenames(options: DirOptions) -> Vec<String> {
+        options
+            .walk(Path::new("src"))
+            .unwrap()
+            .into_iter()
+            .map(|(_, filename)| filename)
+            .collect()
+    }
+
+    #[test]
+    fn test_walk() {
+        let all = filenames(DirOptions::default());
+        assert!(all.contains(&"src/lib.rs".to_string()));
+        assert!(all.contains(&"src/bin/multipart.rs".to_string()));
+
+        let flat = filenames(DirOptions::default().recursive(false));
+        assert!(flat.contains(&"src/lib.rs".to_string()));
+        assert!(!flat.contains(&"src/bin/multipart.rs".to_string()));
+    }
+
+    #[test]
+    fn test_filters() {
+        let basenames = filenames(
+            DirOptions::default()
+                .include("*.rs")
+                .unwrap()
+                .exclude("bin")
+                .unwrap()
+                .exclude("form*")
+                .unwrap()
+                .filename_style(FilenameStyle::Basename),
+        );
+        assert!(basenames.contains(&"lib.rs".to_string()));
+        assert!(!basenames.contains(&"multipart.rs".to_string()));
+        assert!(!basenames.contains(&"form.rs".to_string()));
+
+        let nested = filenames(DirOptions::default().include("bin/*.rs").unwrap());
+        assert_eq!(vec!["src/bin/multipart.rs".to_string()], nested);
+        let patterns = [compile("bin/*.rs").unwrap()];
+        assert!(matches_any(&patterns, "y.rs", "bin/y.rs"));
+        assert!(!matches_any(&patterns, "y.rs", "bin/x/y.rs"));
+        let patterns = [compile("bin/**/*.rs").unwrap()];
+        assert!(matches_any(&patterns, "y.rs", "bin/x/y.rs"));
+
+        match DirOptions::default().include("[") {
+            Err(Error::Glob { position: 0, .. }) => (),
+            result => panic!("unexpected result: {:?}", result.map(|_| ())),
+        }
+    }
+}
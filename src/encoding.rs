@@ -0,0 +1,185 @@
+// Copyright 2018 rust-multipart-rfc7578 Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+//
+
+use std::{
+    io::{self, Read},
+    sync::Arc,
+};
+
+/// A compression applied to the data of a part, and announced with a
+/// `Content-Encoding` header. Each encoding is enabled by the feature of
+/// the same name.
+///
+/// [See](https://www.rfc-editor.org/rfc/rfc9110#section-8.4.1).
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Encoding {
+    /// Gzip.
+    ///
+    #[cfg(feature = "gzip")]
+    Gzip,
+
+    /// Deflate in the zlib format, as HTTP defines it.
+    ///
+    #[cfg(feature = "deflate")]
+    Deflate,
+
+    /// Brotli.
+    ///
+    #[cfg(feature = "brotli")]
+    Brotli,
+
+    /// Zstandard.
+    ///
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+impl Encoding {
+    /// Returns the token of the encoding in the `Content-Encoding` header.
+    ///
+    pub fn token(self) -> &'static str {
+        match self {
+            #[cfg(feature = "gzip")]
+            Encoding::Gzip => "gzip",
+            #[cfg(feature = "deflate")]
+            Encoding::Deflate => "deflate",
+            #[cfg(feature = "brotli")]
+            Encoding::Brotli => "br",
+            #[cfg(feature = "zstd")]
+            Encoding::Zstd => "zstd",
+        }
+    }
+
+    /// Wraps a reader, compressing the data as it is read. An encoder that
+    /// cannot be set up reports its error on the first read.
+    ///
+    pub(crate) fn encoder<'a, R>(self, read: R) -> Box<dyn 'a + Read + Send>
+    where
+        R: 'a + Read + Send,
+    {
+        match self {
+            #[cfg(feature = "gzip")]
+            Encoding::Gzip => Box::new(flate2::read::GzEncoder::new(
+                read,
+                flate2::Compression::default(),
+            )),
+            #[cfg(feature = "deflate")]
+            Encoding::Deflate => Box::new(flate2::read::ZlibEncoder::new(
+                read,
+                flate2::Compression::default(),
+            )),
+            #[cfg(feature = "brotli")]
+            Encoding::Brotli => Box::new(brotli::CompressorReader::new(read, 4096, 5, 22)),
+            #[cfg(feature = "zstd")]
+            Encoding::Zstd => match zstd::stream::read::Encoder::new(read, 0) {
+                Ok(encoder) => Box::new(encoder),
+                Err(e) => Box::new(Failed(Some(e))),
+            },
+        }
+    }
+
+    /// Compresses in-memory data.
+    ///
+    pub(crate) fn encode<R>(self, read: R) -> io::Result<Arc<[u8]>>
+    where
+        R: Read + Send,
+    {
+        let mut data = vec![];
+        self.encoder(read).read_to_end(&mut data)?;
+        Ok(data.into())
+    }
+}
+
+/// A reader failing with an error once, and then empty.
+///
+#[cfg(feature = "zstd")]
+struct Failed(Option<io::Error>);
+
+#[cfg(feature = "zstd")]
+impl Read for Failed {
+    #[inline]
+    fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+        match self.0.take() {
+            Some(e) => Err(e),
+            None => Ok(0),
+        }
+    }
+}
+
+/// How the data of a part is compressed.
+///
+#[derive(Clone)]
+pub(crate) enum PartEncoding {
+    /// The data was in memory and compressed up front, so its length is
+    /// known.
+    ///
+    Precompressed(Encoding, Arc<[u8]>),
+
+    /// The data is compressed while it is streamed.
+    ///
+    Streaming(Encoding),
+}
+
+impl PartEncoding {
+    /// Returns the token of the encoding in the `Content-Encoding` header.
+    ///
+    #[inline]
+    pub(crate) fn token(&self) -> &'static str {
+        match *self {
+            PartEncoding::Precompressed(encoding, _) | PartEncoding::Streaming(encoding) => {
+                encoding.token()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Encoding;
+    use std::io::{Cursor, Read};
+
+    fn decode(encoding: Encoding, data: &[u8]) -> Vec<u8> {
+        let mut decoded = vec![];
+        match encoding {
+            #[cfg(feature = "gzip")]
+            Encoding::Gzip => flate2::read::GzDecoder::new(data).read_to_end(&mut decoded),
+            #[cfg(feature = "deflate")]
+            Encoding::Deflate => flate2::read::ZlibDecoder::new(data).read_to_end(&mut decoded),
+            #[cfg(feature = "brotli")]
+            Encoding::Brotli => brotli::Decompressor::new(data, 4096).read_to_end(&mut decoded),
+            #[cfg(feature = "zstd")]
+            Encoding::Zstd => zstd::stream::read::Decoder::new(data)
+                .unwrap()
+                .read_to_end(&mut decoded),
+        }
+        .unwrap();
+        decoded
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let encodings = [
+            #[cfg(feature = "gzip")]
+            Encoding::Gzip,
+            #[cfg(feature = "deflate")]
+            Encoding::Deflate,
+            #[cfg(feature = "brotli")]
+            Encoding::Brotli,
+            #[cfg(feature = "zstd")]
+            Encoding::Zstd,
+        ];
+        let data = "id,name\n".repeat(1000);
+        for &encoding in &encodings {
+            let encoded = encoding.encode(Cursor::new(data.as_bytes())).unwrap();
+            assert!(encoded.len() < data.len());
+            assert_eq!(data.as_bytes(), &decode(encoding, &encoded)[..]);
+        }
+    }
+}
@@ -0,0 +1,411 @@
+// Copyright 2018 rust-multipart-rfc7578 Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+//
+
+use std::{
+    error, fmt,
+    io::{self, Read},
+    path::PathBuf,
+};
+
+/// The errors of building, writing out and parsing a form.
+///
+/// Readers and streams still report an `io::Error`, so they can be used
+/// wherever the standard traits are expected. The `io::Error` wraps this
+/// error, and converting it back with `Error::from` recovers it.
+///
+/// # Examples
+///
+/// ```
+/// use multipart_rfc7578::{Error, Form};
+///
+/// let mut form = Form::default();
+///
+/// match form.add_file("file", ".") {
+///     Err(Error::NotAFile { path }) => println!("{} is a directory", path.display()),
+///     _ => (),
+/// }
+/// ```
+///
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// An error that is not related to a specific part.
+    ///
+    Io(io::Error),
+
+    /// A file could not be opened.
+    ///
+    Open { path: PathBuf, source: io::Error },
+
+    /// A path passed as a file is a directory, or something else that has
+    /// no content.
+    ///
+    NotAFile { path: PathBuf },
+
+    /// Reading the data of a part failed.
+    ///
+    Read {
+        index: usize,
+        name: String,
+        source: io::Error,
+    },
+
+    /// A part did not produce as many bytes as its declared length. For a
+    /// part that is too long, reading stops at the first byte past the
+    /// declared length, so `actual` is one more than `expected`.
+    ///
+    LengthMismatch {
+        index: usize,
+        name: String,
+        expected: u64,
+        actual: u64,
+    },
+
+    /// A file changed size between the time it was added and the time it
+    /// was opened.
+    ///
+    FileChanged {
+        path: PathBuf,
+        expected: u64,
+        actual: u64,
+    },
+
+    /// A value could not be serialized into a part.
+    ///
+    Serialize {
+        name: String,
+        source: Box<dyn error::Error + Send + Sync>,
+    },
+
+    /// A directory cannot be streamed as an archive.
+    ///
+    Archive { path: PathBuf, message: String },
+
+    /// A part is read from a one-shot reader, so the form cannot be
+    /// replayed.
+    ///
+    NotReplayable { index: usize, name: String },
+
+    /// A field spec is malformed. The position is the byte offset in the
+    /// spec where the problem was found.
+    ///
+    Spec {
+        spec: String,
+        position: usize,
+        message: String,
+    },
+
+    /// A glob pattern is malformed. The position is the byte offset in the
+    /// pattern where the problem was found.
+    ///
+    Glob {
+        pattern: String,
+        position: usize,
+        message: String,
+    },
+
+    /// A content type is not `multipart/form-data` with a boundary.
+    ///
+    ContentType { content_type: String },
+
+    /// A multipart body is malformed. The position is the byte offset in
+    /// the body where the problem was found.
+    ///
+    Parse { position: usize, message: String },
+}
+
+impl Error {
+    /// Returns the kind of the `io::Error` this error converts to.
+    ///
+    pub fn kind(&self) -> io::ErrorKind {
+        match *self {
+            Error::Io(ref e)
+            | Error::Open { source: ref e, .. }
+            | Error::Read { source: ref e, .. } => e.kind(),
+            Error::NotAFile { .. }
+            | Error::Archive { .. }
+            | Error::NotReplayable { .. }
+            | Error::Spec { .. }
+            | Error::Glob { .. }
+            | Error::ContentType { .. } => io::ErrorKind::InvalidInput,
+            Error::LengthMismatch { .. }
+            | Error::FileChanged { .. }
+            | Error::Serialize { .. }
+            | Error::Parse { .. } => io::ErrorKind::InvalidData,
+        }
+    }
+
+    /// Returns the index of the part involved, if any.
+    ///
+    pub fn index(&self) -> Option<usize> {
+        match *self {
+            Error::Read { index, .. }
+            | Error::LengthMismatch { index, .. }
+            | Error::NotReplayable { index, .. } => Some(index),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref e) => e.fmt(f),
+            Error::Open {
+                ref path,
+                ref source,
+            } => write!(f, "cannot open `{}`: {}", path.display(), source),
+            Error::NotAFile { ref path } => {
+                write!(f, "expected a file not directory: `{}`", path.display())
+            }
+            Error::Read {
+                index,
+                ref name,
+                ref source,
+            } => write!(f, "cannot read part {} (`{}`): {}", index, name, source),
+            Error::LengthMismatch {
+                index,
+                ref name,
+                expected,
+                actual,
+            } => {
+                if actual > expected {
+                    write!(
+                        f,
+                        "part {} (`{}`) is longer than its declared length of {} bytes",
+                        index, name, expected
+                    )
+                } else {
+                    write!(
+                        f,
+                        "part {} (`{}`) is {} bytes long, but declared a length of {} bytes",
+                        index, name, actual, expected
+                    )
+                }
+            }
+            Error::FileChanged {
+                ref path,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "`{}` changed size from {} to {} bytes since it was added",
+                path.display(),
+                expected,
+                actual
+            ),
+            Error::Serialize {
+                ref name,
+                ref source,
+            } => write!(f, "cannot serialize part `{}`: {}", name, source),
+            Error::Archive {
+                ref path,
+                ref message,
+            } => write!(f, "cannot archive `{}`: {}", path.display(), message),
+            Error::NotReplayable { index, ref name } => write!(
+                f,
+                "part {} (`{}`) is read from a reader and cannot be replayed",
+                index, name
+            ),
+            Error::Spec {
+                ref spec,
+                position,
+                ref message,
+            } => write!(
+                f,
+                "invalid field spec `{}` at byte {}: {}",
+                spec, position, message
+            ),
+            Error::Glob {
+                ref pattern,
+                position,
+                ref message,
+            } => write!(
+                f,
+                "invalid glob pattern `{}` at byte {}: {}",
+                pattern, position, message
+            ),
+            Error::ContentType { ref content_type } => write!(
+                f,
+                "expected a multipart content type with a boundary: `{}`",
+                content_type
+            ),
+            Error::Parse {
+                position,
+                ref message,
+            } => write!(
+                f,
+                "malformed multipart body at byte {}: {}",
+                position, message
+            ),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Io(ref e)
+            | Error::Open { source: ref e, .. }
+            | Error::Read { source: ref e, .. } => Some(e),
+            Error::Serialize { ref source, .. } => Some(&**source),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    /// Recovers an error wrapped in an `io::Error`, or wraps the
+    /// `io::Error` otherwise.
+    ///
+    fn from(e: io::Error) -> Self {
+        if !is_wrapped(&e) {
+            return Error::Io(e);
+        }
+        match e.into_inner().map(|inner| inner.downcast::<Error>()) {
+            Some(Ok(inner)) => *inner,
+            _ => unreachable!("the error was checked to wrap an `Error`"),
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Io(e) => e,
+            e => io::Error::new(e.kind(), e),
+        }
+    }
+}
+
+/// Returns true if the `io::Error` already wraps an `Error`.
+///
+#[inline]
+fn is_wrapped(e: &io::Error) -> bool {
+    e.get_ref().is_some_and(|inner| inner.is::<Error>())
+}
+
+/// Returns true if the `io::Error` wraps an `Error` naming a part.
+///
+#[inline]
+fn names_part(e: &io::Error) -> bool {
+    e.get_ref()
+        .and_then(|inner| inner.downcast_ref::<Error>())
+        .is_some_and(|inner| inner.index().is_some())
+}
+
+/// A reader reporting its errors as errors reading a part. Errors of files
+/// opened lazily, which only name their path, are wrapped too.
+///
+/// Interruptions are passed through, since they are retried by callers.
+///
+pub(crate) struct PartReader<R> {
+    inner: R,
+    index: usize,
+    name: String,
+}
+
+impl<R: Read> PartReader<R> {
+    #[inline]
+    pub(crate) fn new(inner: R, index: usize, name: String) -> Self {
+        Self { inner, index, name }
+    }
+}
+
+impl<R: Read> Read for PartReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf).map_err(|e| match e.kind() {
+            io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock => e,
+            _ if names_part(&e) => e,
+            _ => Error::Read {
+                index: self.index,
+                name: self.name.clone(),
+                source: e,
+            }
+            .into(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Error, PartReader};
+    use std::io::{self, Read};
+
+    struct Failing;
+
+    impl Read for Failing {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::new(io::ErrorKind::BrokenPipe, "broken"))
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let err = PartReader::new(Failing, 2, "file".to_string())
+            .read(&mut [0; 4])
+            .unwrap_err();
+        assert_eq!(io::ErrorKind::BrokenPipe, err.kind());
+        match Error::from(err) {
+            Error::Read {
+                index: 2,
+                ref name,
+                ref source,
+            } if name == "file" => assert_eq!("broken", source.to_string()),
+            e => panic!("unexpected error: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_file_errors() {
+        struct Changed;
+
+        impl Read for Changed {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(Error::FileChanged {
+                    path: "data.txt".into(),
+                    expected: 3,
+                    actual: 6,
+                }
+                .into())
+            }
+        }
+
+        let err = PartReader::new(Changed, 1, "file".to_string())
+            .read(&mut [0; 4])
+            .unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+        match Error::from(err) {
+            Error::Read {
+                index: 1, source, ..
+            } => match Error::from(source) {
+                Error::FileChanged { expected: 3, .. } => (),
+                e => panic!("unexpected source: {:?}", e),
+            },
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        let err = PartReader::new(
+            PartReader::new(Changed, 1, "file".to_string()),
+            2,
+            "other".to_string(),
+        )
+        .read(&mut [0; 4])
+        .unwrap_err();
+        assert_eq!(Some(1), Error::from(err).index());
+    }
+
+    #[test]
+    fn test_plain_io() {
+        let err = io::Error::other("other");
+        match Error::from(err) {
+            Error::Io(ref e) => assert_eq!("other", e.to_string()),
+            e => panic!("unexpected error: {:?}", e),
+        }
+    }
+}
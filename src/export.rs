@@ -0,0 +1,268 @@
+// Copyright 2018 rust-multipart-rfc7578 Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+//
+
+use crate::part::Part;
+use crate::profile::Profile;
+use std::fmt::Write;
+
+/// The arguments of a `curl` command sending a form, as returned by
+/// [`Form::to_curl`](struct.Form.html#method.to_curl).
+///
+/// Every part becomes a `-F` option. Text fields and files are read by
+/// curl itself. The data of one other part, such as a reader or
+/// in-memory bytes, is read from stdin as it is sent, after any
+/// compression. A form with several such parts is sent as a raw body
+/// with `--data-binary @-` instead, and the body written out by the form
+/// has to be piped into curl.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CurlCommand {
+    args: Vec<String>,
+    stdin: Stdin,
+}
+
+/// What a curl command reads from stdin.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Stdin {
+    Nothing,
+    Part(usize),
+    Body,
+}
+
+impl CurlCommand {
+    /// Builds the command for the parts of a form.
+    ///
+    pub(crate) fn new(parts: &[Part], content_type: String, profile: &Profile) -> Self {
+        let mut stdin = Stdin::Nothing;
+        let mut args = vec![];
+        for (index, part) in parts.iter().enumerate() {
+            let headers = part.extra_headers(profile);
+            let [option, value] = match form_option(part, &headers) {
+                Some(option) => option,
+                None if stdin == Stdin::Nothing => {
+                    stdin = Stdin::Part(index);
+                    stdin_option(part, &headers)
+                }
+                None => {
+                    return Self {
+                        args: vec![
+                            "-H".to_string(),
+                            format!("Content-Type: {}", content_type),
+                            "--data-binary".to_string(),
+                            "@-".to_string(),
+                        ],
+                        stdin: Stdin::Body,
+                    };
+                }
+            };
+            args.push(option);
+            args.push(value);
+        }
+        Self { args, stdin }
+    }
+
+    /// Returns the arguments, without the URL.
+    ///
+    #[inline]
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    /// Returns true if curl reads the data of a part, or the whole body
+    /// of the form, from stdin.
+    ///
+    #[inline]
+    pub fn reads_stdin(&self) -> bool {
+        self.stdin != Stdin::Nothing
+    }
+
+    /// Returns the index of the part whose data has to be piped into
+    /// curl, if a single part is read from stdin. When the command reads
+    /// stdin and this is `None`, the whole body has to be piped.
+    ///
+    #[inline]
+    pub fn stdin_part(&self) -> Option<usize> {
+        match self.stdin {
+            Stdin::Part(index) => Some(index),
+            _ => None,
+        }
+    }
+
+    /// Returns a command line for a POSIX shell, quoting every argument.
+    ///
+    pub fn to_shell(&self, url: &str) -> String {
+        let mut command = "curl".to_string();
+        for arg in self.args.iter().map(String::as_str).chain(Some(url)) {
+            command.push(' ');
+            command.push_str(&shell_quote(arg));
+        }
+        command
+    }
+}
+
+/// Returns the option sending a part, if curl can read it by itself.
+///
+fn form_option(part: &Part, headers: &[String]) -> Option<[String; 2]> {
+    let info = part.info();
+    if let Some(text) = part.text() {
+        // `-F` treats values starting with `@` or `<` as files, strips the
+        // quotes of values starting with `"`, and parses anything after a
+        // `;`. `--form-string` does not, but it cannot carry headers
+        // either.
+        //
+        return if !text.starts_with(['@', '<', '"']) && !text.contains(';') {
+            let mut value = format!("{}={}", info.name(), text);
+            push_headers(&mut value, headers);
+            Some(["-F".to_string(), value])
+        } else if headers.is_empty() {
+            Some([
+                "--form-string".to_string(),
+                format!("{}={}", info.name(), text),
+            ])
+        } else {
+            None
+        };
+    }
+    let path = part.path()?;
+    let mut value = format!(
+        "{}=@{};type={}",
+        info.name(),
+        form_quote(&path.to_string_lossy()),
+        info.content_type()
+    );
+    if let Some(filename) = info.filename() {
+        let _ = write!(value, ";filename={}", form_quote(filename));
+    }
+    push_headers(&mut value, headers);
+    Some(["-F".to_string(), value])
+}
+
+/// Returns the option sending a part read from stdin. Parts with a
+/// filename are uploaded as files, and others as field contents, since
+/// curl names files read from stdin `-`.
+///
+fn stdin_option(part: &Part, headers: &[String]) -> [String; 2] {
+    let info = part.info();
+    let mut value = match info.filename() {
+        Some(filename) => format!(
+            "{}=@-;type={};filename={}",
+            info.name(),
+            info.content_type(),
+            form_quote(filename)
+        ),
+        None => format!("{}=<-;type={}", info.name(), info.content_type()),
+    };
+    push_headers(&mut value, headers);
+    ["-F".to_string(), value]
+}
+
+/// Appends a `headers` modifier to a `-F` value for every header.
+///
+fn push_headers(value: &mut String, headers: &[String]) {
+    for header in headers {
+        let _ = write!(value, ";headers={}", quote(header));
+    }
+}
+
+/// Quotes a path or filename in a `-F` value, if it contains characters
+/// that curl would parse.
+///
+fn form_quote(value: &str) -> String {
+    if value.contains([';', ',', '"']) {
+        quote(value)
+    } else {
+        value.to_string()
+    }
+}
+
+/// Quotes a value in a `-F` option.
+///
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Quotes an argument for a POSIX shell.
+///
+fn shell_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+/// Renders the `postData` object of a HAR entry for the parts of a form.
+///
+/// Text fields carry their value, and other parts their filename and
+/// content type.
+///
+/// [See](http://www.softwareishard.com/blog/har-12-spec/#postData).
+///
+pub(crate) fn har_post_data(parts: &[Part], content_type: String) -> String {
+    let params: Vec<_> = parts
+        .iter()
+        .map(|part| {
+            let info = part.info();
+            let mut param = format!("{{\"name\":{}", json_string(info.name()));
+            match part.text() {
+                Some(text) => {
+                    let _ = write!(param, ",\"value\":{}", json_string(text));
+                }
+                None => {
+                    if let Some(filename) = info.filename() {
+                        let _ = write!(param, ",\"fileName\":{}", json_string(filename));
+                    }
+                    let _ = write!(
+                        param,
+                        ",\"contentType\":{}",
+                        json_string(info.content_type().as_ref())
+                    );
+                }
+            }
+            param.push('}');
+            param
+        })
+        .collect();
+    format!(
+        "{{\"mimeType\":{},\"params\":[{}]}}",
+        json_string(&content_type),
+        params.join(",")
+    )
+}
+
+/// Encodes a JSON string.
+///
+fn json_string(value: &str) -> String {
+    let mut json = String::with_capacity(value.len() + 2);
+    json.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(json, "\\u{:04x}", c as u32);
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{form_quote, json_string, shell_quote};
+
+    #[test]
+    fn test_quoting() {
+        assert_eq!("'it'\\''s'", shell_quote("it's"));
+        assert_eq!("a.txt", form_quote("a.txt"));
+        assert_eq!("\"a;\\\"b\\\".txt\"", form_quote("a;\"b\".txt"));
+        assert_eq!("\"a\\\"b\\n\\u0001\"", json_string("a\"b\n\u{1}"));
+    }
+}
@@ -7,27 +7,54 @@
 // copied, modified, or distributed except according to those terms.
 //
 
+#[cfg(feature = "archive")]
+use crate::archive::{Archive, ArchiveFormat};
 use crate::boundary_generator::{BoundaryGenerator, RandomAsciiGenerator};
+#[cfg(any(
+    feature = "md5",
+    feature = "sha1",
+    feature = "sha256",
+    feature = "crc32c"
+))]
+use crate::checksum::{self, Algorithm, Checksums, Hasher};
+use crate::chunk::{ChunkSource, IntoChunk};
+use crate::curl::{self, CurlHeader, CurlSpec, CurlValue};
+#[cfg(feature = "dir")]
+use crate::dir::DirOptions;
+#[cfg(any(
+    feature = "gzip",
+    feature = "deflate",
+    feature = "brotli",
+    feature = "zstd"
+))]
+use crate::encoding::Encoding;
+use crate::error::Error;
+use crate::export::{self, CurlCommand};
 use crate::form_reader::FormReader;
-use crate::part::{Inner, Part};
+use crate::length::LengthPolicy;
+use crate::observer::{Event, Observer, Observers};
+use crate::part::{OwnedPart, Part, PartContentLength, PartInfo, WriteOptions};
+use crate::preview::PreviewOptions;
+use crate::profile::Profile;
+use crate::source::{FactorySource, PartSource, PathSource, ReadFactory, ReaderSource, SeekSource};
 use crate::CRLF;
 use mime::Mime;
 use std::borrow::Borrow;
 use std::{
-    fmt::Display,
-    fs::File,
-    io::{self, Cursor, Read},
+    cmp::Ordering,
+    fmt::{self, Display},
+    fs,
+    io::{self, Cursor, Read, Seek},
     path::Path,
     str::FromStr,
+    sync::{mpsc, Arc},
 };
 
-#[cfg(any(feature = "hyper", feature = "awc"))]
+#[cfg(feature = "futures")]
 use crate::body::Body;
 #[cfg(any(feature = "hyper", feature = "awc"))]
 use http::header::{CONTENT_LENGTH, CONTENT_TYPE};
 
-// use error::Error;
-
 /// Implements the multipart/form-data media type as described by
 /// RFC 7578.
 ///
@@ -41,6 +68,36 @@ pub struct Form<'a> {
     /// [See](https://tools.ietf.org/html/rfc7578#section-4.1).
     ///
     boundary: String,
+
+    /// Observers notified while the form is written out.
+    ///
+    observers: Observers<'a>,
+
+    /// The checksums of the parts, recorded while the form is written out.
+    ///
+    #[cfg(any(
+        feature = "md5",
+        feature = "sha1",
+        feature = "sha256",
+        feature = "crc32c"
+    ))]
+    checksums: Checksums,
+
+    /// What to do with parts that do not match their declared length.
+    ///
+    length_policy: LengthPolicy,
+
+    /// Which parts carry a `Content-Length` header.
+    ///
+    part_content_length: PartContentLength,
+
+    /// How headers are formatted.
+    ///
+    profile: Profile,
+
+    /// Whether files are checked for size changes when they are opened.
+    ///
+    detect_file_changes: bool,
 }
 
 impl<'a> Default for Form<'a> {
@@ -52,6 +109,18 @@ impl<'a> Default for Form<'a> {
     }
 }
 
+impl<'a> fmt::Debug for Form<'a> {
+    /// Shows the boundary and the metadata of every part, without reading
+    /// their data.
+    ///
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Form")
+            .field("boundary", &self.boundary)
+            .field("parts", &self.parts().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
 impl<'a> Form<'a> {
     /// Creates a new form with the specified boundary generator function.
     ///
@@ -80,9 +149,93 @@ impl<'a> Form<'a> {
         Self {
             parts: vec![],
             boundary: G::generate_boundary(),
+            observers: Observers::default(),
+            #[cfg(any(
+                feature = "md5",
+                feature = "sha1",
+                feature = "sha256",
+                feature = "crc32c"
+            ))]
+            checksums: Checksums::default(),
+            length_policy: LengthPolicy::default(),
+            part_content_length: PartContentLength::default(),
+            profile: Profile::default(),
+            detect_file_changes: false,
+        }
+    }
+
+    /// Creates a new form formatted with `profile`, whose boundary is
+    /// generated like the client the profile matches.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multipart_rfc7578::{Form, Profile};
+    ///
+    /// let form = Form::with_profile(Profile::curl());
+    ///
+    /// assert!(form
+    ///     .content_type()
+    ///     .starts_with("multipart/form-data; boundary=------------------------"));
+    /// ```
+    ///
+    #[inline]
+    pub fn with_profile(profile: Profile) -> Self {
+        Form {
+            boundary: profile.generate_boundary(),
+            profile,
+            ..Form::default()
         }
     }
 
+    /// Adds a part whose data comes from a [`PartSource`](trait.PartSource.html).
+    /// Unless they are given, the filename and content type are the ones
+    /// of the source.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multipart_rfc7578::{Form, PathSource};
+    ///
+    /// let mut form = Form::default();
+    ///
+    /// form.add_source("text", String::from("Hello World!"), None::<&str>, None);
+    /// form.add_source::<_, &str, _>("file", PathSource::new("Cargo.toml").unwrap(), None, None);
+    /// assert_eq!(Some("Cargo.toml"), form.part(1).unwrap().filename());
+    /// ```
+    ///
+    pub fn add_source<F, G, S>(
+        &mut self,
+        name: F,
+        source: S,
+        filename: Option<G>,
+        mime: Option<Mime>,
+    ) where
+        F: Display,
+        G: Into<String>,
+        S: 'a + PartSource<'a>,
+    {
+        let filename = filename.map(Into::into).or_else(|| source.filename());
+        self.push_source(name, Box::new(source), filename, mime);
+    }
+
+    /// Adds a part with the specified filename, or none. Every part is
+    /// added through here, so it behaves the same whichever method added
+    /// its source.
+    ///
+    #[inline]
+    fn push_source<F>(
+        &mut self,
+        name: F,
+        source: Box<dyn 'a + PartSource<'a>>,
+        filename: Option<String>,
+        mime: Option<Mime>,
+    ) where
+        F: Display,
+    {
+        self.parts.push(Part::new(source, name, mime, filename));
+    }
+
     /// Adds a text part to the Form.
     ///
     /// # Examples
@@ -101,12 +254,7 @@ impl<'a> Form<'a> {
         N: Display,
         T: Into<String>,
     {
-        self.parts.push(Part::new::<_, String>(
-            Inner::Text(text.into()),
-            name,
-            None,
-            None,
-        ))
+        self.add_source(name, text.into(), None::<String>, None);
     }
 
     /// Adds a readable part to the Form.
@@ -138,14 +286,7 @@ impl<'a> Form<'a> {
         G: Into<String>,
         R: 'a + Read + Send,
     {
-        let read = Box::new(read);
-
-        self.parts.push(Part::new::<_, String>(
-            Inner::Read(read, length),
-            name,
-            mime,
-            filename.map(Into::into),
-        ));
+        self.add_source(name, ReaderSource::new(read, length), filename, mime);
     }
 
     /// Adds a readable part to the Form.
@@ -222,30 +363,34 @@ impl<'a> Form<'a> {
         self.add_reader2(name, read, Some(filename), Some(mime), None);
     }
 
-    /// Adds a file, and attempts to derive the mime type.
+    /// Adds a part to the Form whose data is produced by an iterator, such
+    /// as rows generated on the fly. Like a reader, it can only be read
+    /// once. The chunks are not copied when the form is sent as a `Body`,
+    /// unless they are hashed or compressed while they are streamed.
     ///
     /// # Examples
     ///
     /// ```
     /// use multipart_rfc7578::Form;
     ///
+    /// let rows = (0..3).map(|i| format!("{},row {}\n", i, i));
     /// let mut form = Form::default();
     ///
-    /// form.add_file("file", file!()).expect("file to exist");
+    /// form.add_iter("rows", rows, None);
     /// ```
     ///
     #[inline]
-    pub fn add_file<P, F>(&mut self, name: F, path: P) -> io::Result<()>
+    pub fn add_iter<F, I>(&mut self, name: F, chunks: I, length: Option<u64>)
     where
-        P: AsRef<Path>,
         F: Display,
+        I: IntoIterator,
+        I::IntoIter: 'a + Send,
+        I::Item: 'a + IntoChunk,
     {
-        self._add_file(name, path, None)
+        self.add_iter2(name, chunks, None::<&str>, None, length);
     }
 
-    /// Adds a file with the specified mime type to the form.
-    /// If the mime type isn't specified, a mime type will try to
-    /// be derived.
+    /// Adds a part to the Form whose data is produced by an iterator.
     ///
     /// # Examples
     ///
@@ -254,144 +399,1451 @@ impl<'a> Form<'a> {
     /// # extern crate multipart_rfc7578;
     /// #
     /// use multipart_rfc7578::Form;
+    /// use std::io;
     ///
-    /// # fn main() {
+    /// let rows: Vec<io::Result<Vec<u8>>> = vec![Ok(b"id,name\n".to_vec()), Ok(b"1,a\n".to_vec())];
     /// let mut form = Form::default();
     ///
-    /// form.add_file_with_mime("data", "test.csv", mime::TEXT_CSV);
-    /// # }
+    /// form.add_iter2("rows", rows, Some("rows.csv"), Some(mime::TEXT_CSV), Some(12));
+    /// ```
+    ///
+    pub fn add_iter2<F, G, I>(
+        &mut self,
+        name: F,
+        chunks: I,
+        filename: Option<G>,
+        mime: Option<Mime>,
+        length: Option<u64>,
+    ) where
+        F: Display,
+        G: Into<String>,
+        I: IntoIterator,
+        I::IntoIter: 'a + Send,
+        I::Item: 'a + IntoChunk,
+    {
+        let chunks = Box::new(chunks.into_iter().map(IntoChunk::into_chunk));
+
+        self.add_source(name, ChunkSource::new(chunks, length), filename, mime);
+    }
+
+    /// Adds an in-memory part to the Form. The data is shared, not copied,
+    /// when the form is replayed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multipart_rfc7578::Form;
+    ///
+    /// let mut form = Form::default();
+    ///
+    /// form.add_bytes("input", &b"Hello World!"[..]);
+    /// form.add_bytes("more", vec![0, 1, 2, 3]);
     /// ```
     ///
     #[inline]
-    pub fn add_file_with_mime<P, F>(&mut self, name: F, path: P, mime: Mime) -> io::Result<()>
+    pub fn add_bytes<F, B>(&mut self, name: F, bytes: B)
     where
-        P: AsRef<Path>,
         F: Display,
+        B: Into<Arc<[u8]>>,
     {
-        self._add_file(name, path, Some(mime))
+        self.add_bytes2(name, bytes, None::<&str>, None);
     }
 
-    /// Internal method for adding a file part to the form.
+    /// Adds an in-memory part to the Form as a file.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multipart_rfc7578::Form;
+    ///
+    /// let mut form = Form::default();
+    ///
+    /// form.add_bytes_file("input", &b"Hello World!"[..], "filename.txt");
+    /// ```
     ///
-    fn _add_file<P, F>(&mut self, name: F, path: P, mime: Option<Mime>) -> io::Result<()>
+    #[inline]
+    pub fn add_bytes_file<F, G, B>(&mut self, name: F, bytes: B, filename: G)
     where
-        P: AsRef<Path>,
         F: Display,
+        G: Into<String>,
+        B: Into<Arc<[u8]>>,
     {
-        let f = File::open(&path)?;
-        let mime = match mime {
-            Some(mime) => Some(mime),
-            None => match path.as_ref().extension() {
-                Some(ext) => Mime::from_str(ext.to_string_lossy().borrow()).ok(),
-                None => None,
-            },
-        };
-        let len = match f.metadata() {
-            // If the path is not a file, it can't be uploaded because there
-            // is no content.
-            //
-            Ok(ref meta) if !meta.is_file() => Err(io::Error::new(
-                io::ErrorKind::InvalidInput,
-                "expected a file not directory",
-            )),
-
-            // If there is some metadata on the file, try to derive some
-            // header values.
-            //
-            Ok(ref meta) => Ok(Some(meta.len())),
-
-            // The file metadata could not be accessed. This MIGHT not be an
-            // error, if the file could be opened.
-            //
-            Err(e) => Err(e),
-        }?;
-
-        let read = Box::new(f);
-
-        self.parts.push(Part::new(
-            Inner::Read(read, len),
-            name,
-            mime,
-            Some(path.as_ref().as_os_str().to_string_lossy()),
-        ));
+        self.add_bytes2(name, bytes, Some(filename), None);
+    }
 
-        Ok(())
+    /// Adds an in-memory part to the Form.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate mime;
+    /// # extern crate multipart_rfc7578;
+    /// #
+    /// use multipart_rfc7578::Form;
+    ///
+    /// let mut form = Form::default();
+    ///
+    /// form.add_bytes2("input", &b"Hello World!"[..], Some("filename.txt"), Some(mime::TEXT_PLAIN));
+    /// ```
+    ///
+    pub fn add_bytes2<F, G, B>(
+        &mut self,
+        name: F,
+        bytes: B,
+        filename: Option<G>,
+        mime: Option<Mime>,
+    ) where
+        F: Display,
+        G: Into<String>,
+        B: Into<Arc<[u8]>>,
+    {
+        let bytes: Arc<[u8]> = bytes.into();
+
+        self.add_source(name, bytes, filename, mime);
     }
 
-    /// get boundary as content type string
+    /// Adds a part holding a value serialized as JSON, with the
+    /// `application/json` content type.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multipart_rfc7578::Form;
+    /// use std::collections::BTreeMap;
+    ///
+    /// let mut form = Form::default();
+    /// let mut metadata = BTreeMap::new();
+    ///
+    /// metadata.insert("title", "Hello World!");
+    /// form.add_json("metadata", &metadata).unwrap();
+    /// ```
+    ///
+    #[cfg(feature = "json")]
     #[inline]
-    pub fn content_type(&self) -> String {
-        format!("multipart/form-data; boundary=\"{}\"", &self.boundary)
+    pub fn add_json<F, T>(&mut self, name: F, value: &T) -> Result<(), Error>
+    where
+        F: Display,
+        T: ?Sized + serde::Serialize,
+    {
+        self.add_json2::<_, String, _>(name, value, None, false)
     }
 
-    #[inline]
-    fn boundary_string(&self) -> String {
-        format!("--{}{}", self.boundary, CRLF)
+    /// Adds a part holding a value serialized as JSON. If `charset` is set,
+    /// the content type is `application/json; charset=utf-8`, which some
+    /// servers require.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multipart_rfc7578::Form;
+    ///
+    /// let mut form = Form::default();
+    ///
+    /// form.add_json2("tags", &["a", "b"], Some("tags.json"), true).unwrap();
+    /// ```
+    ///
+    #[cfg(feature = "json")]
+    pub fn add_json2<F, G, T>(
+        &mut self,
+        name: F,
+        value: &T,
+        filename: Option<G>,
+        charset: bool,
+    ) -> Result<(), Error>
+    where
+        F: Display,
+        G: Into<String>,
+        T: ?Sized + serde::Serialize,
+    {
+        let mime = if charset {
+            "application/json; charset=utf-8".parse().unwrap()
+        } else {
+            mime::APPLICATION_JSON
+        };
+        self.add_serialized(name, serde_json::to_vec(value), filename, mime)
     }
 
+    /// Adds a part holding a value serialized as CBOR, with the
+    /// `application/cbor` content type.
+    ///
+    #[cfg(feature = "cbor")]
     #[inline]
-    fn final_boundary_string(&self) -> String {
-        format!("--{}--{}", self.boundary, CRLF)
+    pub fn add_cbor<F, T>(&mut self, name: F, value: &T) -> Result<(), Error>
+    where
+        F: Display,
+        T: ?Sized + serde::Serialize,
+    {
+        self.add_cbor2::<_, String, _>(name, value, None)
     }
 
-    #[doc(hidden)]
-    pub fn into_reader(self) -> impl Read + 'a {
-        let boundary = Cursor::new(self.boundary_string());
-        let final_boundary = Cursor::new(self.final_boundary_string());
-        let readers = self
-            .parts
-            .into_iter()
-            .map(|part| part.into_reader())
-            .peekable();
-        FormReader::new(boundary, readers, final_boundary)
+    /// Adds a part holding a value serialized as CBOR.
+    ///
+    #[cfg(feature = "cbor")]
+    pub fn add_cbor2<F, G, T>(
+        &mut self,
+        name: F,
+        value: &T,
+        filename: Option<G>,
+    ) -> Result<(), Error>
+    where
+        F: Display,
+        G: Into<String>,
+        T: ?Sized + serde::Serialize,
+    {
+        let mut data = vec![];
+        let result = ciborium::into_writer(value, &mut data).map(|()| data);
+        let mime = "application/cbor".parse().unwrap();
+        self.add_serialized(name, result, filename, mime)
     }
 
+    /// Adds a part holding a value serialized as MessagePack, with the
+    /// `application/msgpack` content type. Structs are written as maps, so
+    /// the field names are kept.
+    ///
+    #[cfg(feature = "msgpack")]
     #[inline]
-    fn boundary_len(&self) -> u64 {
-        (self.boundary.len() + 4) as u64
+    pub fn add_msgpack<F, T>(&mut self, name: F, value: &T) -> Result<(), Error>
+    where
+        F: Display,
+        T: ?Sized + serde::Serialize,
+    {
+        self.add_msgpack2::<_, String, _>(name, value, None)
     }
 
-    /// get content length
-    pub fn content_length(&self) -> Option<u64> {
-        let boundary_len = self.boundary_len() + 2;
-        self.parts.iter().try_fold(boundary_len, |sum, part| {
-            part.content_length().map(|len| sum + len + boundary_len)
-        })
+    /// Adds a part holding a value serialized as MessagePack.
+    ///
+    #[cfg(feature = "msgpack")]
+    pub fn add_msgpack2<F, G, T>(
+        &mut self,
+        name: F,
+        value: &T,
+        filename: Option<G>,
+    ) -> Result<(), Error>
+    where
+        F: Display,
+        G: Into<String>,
+        T: ?Sized + serde::Serialize,
+    {
+        let mime = "application/msgpack".parse().unwrap();
+        self.add_serialized(name, rmp_serde::to_vec_named(value), filename, mime)
     }
-}
 
-impl Form<'static> {
-    /// Just for documentation.
-    /// Updates a request instance with the multipart Content-Type header
-    /// and the payload data.
+    /// Adds an in-memory part from serialized data, so its length is
+    /// exact, or fails without adding anything.
     ///
-    /// # awc example
+    #[cfg(any(feature = "json", feature = "cbor", feature = "msgpack"))]
+    fn add_serialized<F, G, E>(
+        &mut self,
+        name: F,
+        data: Result<Vec<u8>, E>,
+        filename: Option<G>,
+        mime: Mime,
+    ) -> Result<(), Error>
+    where
+        F: Display,
+        G: Into<String>,
+        E: 'static + std::error::Error + Send + Sync,
+    {
+        let data = data.map_err(|e| Error::Serialize {
+            name: name.to_string(),
+            source: Box::new(e),
+        })?;
+        self.add_bytes2(name, data, filename, Some(mime));
+        Ok(())
+    }
+
+    /// Adds a seekable part to the Form. When the form is replayed, the
+    /// reader is rewound to the position it has now, so `length` should be
+    /// the number of bytes from there to the end.
+    ///
+    /// # Examples
     ///
     /// ```
-    /// # #[cfg(feature = "awc")]
-    /// use awc::Client;
-    /// use multipart_rfc7578::{Form, SetBody};
+    /// use multipart_rfc7578::Form;
+    /// use std::io::Cursor;
     ///
-    /// # #[cfg(feature = "awc")]
-    /// # fn main() {
-    /// let url = "http://localhost:80/upload";
-    /// let req = Client::default().post(url);
+    /// let string = "Hello World!";
     /// let mut form = Form::default();
     ///
-    /// form.add_text("text", "Hello World!");
-    /// let req = form.set_body(req).unwrap();
-    /// # }
-    /// # #[cfg(not(feature = "awc"))]
-    /// # fn main() {
-    /// # }
+    /// form.add_seek_reader("input", Cursor::new(string), Some("filename.txt"), None, Some(string.len() as u64))
+    ///     .unwrap();
     /// ```
     ///
-    /// # Hyper example
-    ///
+    pub fn add_seek_reader<F, G, R>(
+        &mut self,
+        name: F,
+        read: R,
+        filename: Option<G>,
+        mime: Option<Mime>,
+        length: Option<u64>,
+    ) -> Result<(), Error>
+    where
+        F: Display,
+        G: Into<String>,
+        R: 'a + Read + Seek + Send,
+    {
+        self.add_source(name, SeekSource::new(read, length)?, filename, mime);
+
+        Ok(())
+    }
+
+    /// Adds a seekable part to the Form. Its length is measured by seeking
+    /// to the end and back, so the content length of the form stays known,
+    /// and it is rewound when the form is replayed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multipart_rfc7578::Form;
+    /// use std::io::Cursor;
+    ///
+    /// let mut form = Form::default();
+    ///
+    /// form.add_seekable("input", Cursor::new("Hello World!")).unwrap();
+    /// assert!(form.content_length().is_some());
+    /// ```
+    ///
+    #[inline]
+    pub fn add_seekable<F, R>(&mut self, name: F, read: R) -> Result<(), Error>
+    where
+        F: Display,
+        R: 'a + Read + Seek + Send,
+    {
+        self.add_seekable2(name, read, None::<&str>, None)
+    }
+
+    /// Adds a seekable part to the Form as a file, measuring its length.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multipart_rfc7578::Form;
+    /// use std::io::Cursor;
+    ///
+    /// let mut form = Form::default();
+    ///
+    /// form.add_seekable_file("input", Cursor::new("Hello World!"), "filename.txt")
+    ///     .unwrap();
+    /// ```
+    ///
+    #[inline]
+    pub fn add_seekable_file<F, G, R>(&mut self, name: F, read: R, filename: G) -> Result<(), Error>
+    where
+        F: Display,
+        G: Into<String>,
+        R: 'a + Read + Seek + Send,
+    {
+        self.add_seekable2(name, read, Some(filename), None)
+    }
+
+    /// Adds a seekable part to the Form, measuring its length from the
+    /// current position of the reader to its end.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate mime;
+    /// # extern crate multipart_rfc7578;
+    /// #
+    /// use multipart_rfc7578::Form;
+    /// use std::io::Cursor;
+    ///
+    /// let mut form = Form::default();
+    ///
+    /// form.add_seekable2("input", Cursor::new("Hello World!"), Some("filename.txt"), Some(mime::TEXT_PLAIN))
+    ///     .unwrap();
+    /// ```
+    ///
+    pub fn add_seekable2<F, G, R>(
+        &mut self,
+        name: F,
+        read: R,
+        filename: Option<G>,
+        mime: Option<Mime>,
+    ) -> Result<(), Error>
+    where
+        F: Display,
+        G: Into<String>,
+        R: 'a + Read + Seek + Send,
+    {
+        self.add_source(name, SeekSource::measured(read)?, filename, mime);
+
+        Ok(())
+    }
+
+    /// Adds a part to the Form which is read from a new reader every time
+    /// the form is written out. The factory is called once the part is
+    /// reached, and any error it returns is reported by the reader.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multipart_rfc7578::Form;
+    /// use std::io::{Cursor, Read};
+    ///
+    /// let mut form = Form::default();
+    ///
+    /// form.add_reader_factory(
+    ///     "input",
+    ///     || Ok(Box::new(Cursor::new("Hello World!")) as Box<dyn Read + Send>),
+    ///     Some("filename.txt"),
+    ///     None,
+    ///     Some(12),
+    /// );
+    /// ```
+    ///
+    pub fn add_reader_factory<F, G, C>(
+        &mut self,
+        name: F,
+        factory: C,
+        filename: Option<G>,
+        mime: Option<Mime>,
+        length: Option<u64>,
+    ) where
+        F: Display,
+        G: Into<String>,
+        C: Fn() -> io::Result<Box<dyn 'a + Read + Send>> + Send + Sync + 'a,
+    {
+        let factory: ReadFactory<'a> = Arc::new(factory);
+
+        self.add_source(name, FactorySource::new(factory, length), filename, mime);
+    }
+
+    /// Adds a file, and attempts to derive the mime type.
+    ///
+    /// Only the metadata of the file is read here. The file is opened when
+    /// the form reaches the part, and closed once it has been read.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multipart_rfc7578::Form;
+    ///
+    /// let mut form = Form::default();
+    ///
+    /// form.add_file("file", file!()).expect("file to exist");
+    /// ```
+    ///
+    #[inline]
+    pub fn add_file<P, F>(&mut self, name: F, path: P) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+        F: Display,
+    {
+        self._add_file(name, path, None)
+    }
+
+    /// Adds a file with the specified mime type to the form.
+    /// If the mime type isn't specified, a mime type will try to
+    /// be derived.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # extern crate mime;
+    /// # extern crate multipart_rfc7578;
+    /// #
+    /// use multipart_rfc7578::Form;
+    ///
+    /// # fn main() {
+    /// let mut form = Form::default();
+    ///
+    /// form.add_file_with_mime("data", "test.csv", mime::TEXT_CSV);
+    /// # }
+    /// ```
+    ///
+    #[inline]
+    pub fn add_file_with_mime<P, F>(&mut self, name: F, path: P, mime: Mime) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+        F: Display,
+    {
+        self._add_file(name, path, Some(mime))
+    }
+
+    /// Adds every file in a directory as a part named `name`, like
+    /// browsers upload a `webkitdirectory` selection. The mime type of each
+    /// file is derived like in [`add_file`](#method.add_file).
+    ///
+    /// If any file cannot be added, the form is left unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multipart_rfc7578::{DirOptions, Form};
+    ///
+    /// let mut form = Form::default();
+    /// let options = DirOptions::default().include("*.rs").unwrap();
+    ///
+    /// form.add_dir("files", "src", &options).expect("directory to exist");
+    /// ```
+    ///
+    #[cfg(feature = "dir")]
+    pub fn add_dir<P, F>(&mut self, name: F, path: P, options: &DirOptions) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+        F: Display,
+    {
+        let files = options.walk(path.as_ref())?;
+        let len = self.parts.len();
+        for (path, filename) in files {
+            if let Err(e) = self.add_file_part(&name, path, None, Some(filename)) {
+                self.parts.truncate(len);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the files in a directory as a single archive file, which is
+    /// streamed into the form without temporary files. The archive is
+    /// named after the directory, such as `photos.tar`.
+    ///
+    /// The files are listed when the part is added, and read every time
+    /// the form is written out. The length of uncompressed archives is
+    /// known up front.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multipart_rfc7578::{ArchiveFormat, DirOptions, Form};
+    ///
+    /// let mut form = Form::default();
+    ///
+    /// form.add_archive("sources", "src", ArchiveFormat::Tar, &DirOptions::default())
+    ///     .expect("directory to exist");
+    /// assert!(form.content_length().is_some());
+    /// ```
+    ///
+    #[cfg(feature = "archive")]
+    pub fn add_archive<P, F>(
+        &mut self,
+        name: F,
+        path: P,
+        format: ArchiveFormat,
+        options: &DirOptions,
+    ) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+        F: Display,
+    {
+        let archive = Archive::new(path.as_ref(), format, options)?;
+        let dir_name = path
+            .as_ref()
+            .canonicalize()
+            .ok()
+            .and_then(|path| {
+                path.file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+            })
+            .unwrap_or_else(|| "archive".to_string());
+        let len = archive.len();
+        let factory: ReadFactory<'a> = Arc::new(move || Ok(archive.reader()));
+
+        self.add_source(
+            name,
+            FactorySource::new(factory, len),
+            Some(format!("{}.{}", dir_name, format.extension())),
+            Some(format.content_type()),
+        );
+
+        Ok(())
+    }
+
+    /// Internal method for adding a file part to the form.
+    ///
+    fn _add_file<P, F>(&mut self, name: F, path: P, mime: Option<Mime>) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+        F: Display,
+    {
+        let filename = path.as_ref().as_os_str().to_string_lossy().into_owned();
+        self.add_file_part(name, path, mime, Some(filename))
+    }
+
+    /// Adds a file part with the specified filename.
+    ///
+    fn add_file_part<P, F>(
+        &mut self,
+        name: F,
+        path: P,
+        mime: Option<Mime>,
+        filename: Option<String>,
+    ) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+        F: Display,
+    {
+        let mime = match mime {
+            Some(mime) => Some(mime),
+            None => match path.as_ref().extension() {
+                Some(ext) => Mime::from_str(ext.to_string_lossy().borrow()).ok(),
+                None => None,
+            },
+        };
+        // The file is only opened once the part is written out, so large
+        // forms do not hold a descriptor for every file.
+        //
+        let source = PathSource::new(path)?;
+        self.push_source(name, Box::new(source), filename, mime);
+
+        Ok(())
+    }
+
+    /// Adds a field in the syntax of curl's `-F` option, such as
+    /// `name=value`, `name=@file` or `name=<file`, with optional `;type=`,
+    /// `;filename=` and `;headers=` modifiers. See
+    /// [`CurlSpec`](struct.CurlSpec.html) for the syntax.
+    ///
+    /// Like curl, a file upload is named after the last component of its
+    /// path unless `;filename=` is set, and `-` reads from stdin.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multipart_rfc7578::Form;
+    ///
+    /// let mut form = Form::default();
+    ///
+    /// form.add_curl_spec("text=Hello World!").unwrap();
+    /// form.add_curl_spec("file=@Cargo.toml;type=text/plain;headers=\"X-Id: 1\"").unwrap();
+    /// assert!(form.add_curl_spec("file=@\"Cargo.toml").is_err());
+    /// ```
+    ///
+    pub fn add_curl_spec(&mut self, spec: &str) -> Result<(), Error> {
+        let CurlSpec {
+            name,
+            value,
+            content_type,
+            filename,
+            headers,
+            header_positions,
+        } = spec.parse()?;
+        let mut parsed_headers = vec![];
+        for (header, position) in headers.into_iter().zip(header_positions) {
+            let lines = match header {
+                CurlHeader::Inline(line) => vec![line],
+                CurlHeader::File(path) => fs::read_to_string(&path)
+                    .map_err(|source| Error::Open {
+                        path: path.clone(),
+                        source,
+                    })?
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                    .map(String::from)
+                    .collect(),
+            };
+            for line in lines {
+                parsed_headers.push(curl::split_header(&line).ok_or_else(|| Error::Spec {
+                    spec: spec.to_string(),
+                    position,
+                    message: curl::invalid_header(&line),
+                })?);
+            }
+        }
+
+        let stdin = |path: &Path| path == Path::new("-");
+        match value {
+            CurlValue::Text(text) => self.add_source(name, text, filename, content_type),
+            CurlValue::File(ref path) if stdin(path) => self.add_source(
+                name,
+                ReaderSource::new(io::stdin(), None),
+                filename,
+                content_type,
+            ),
+            CurlValue::File(path) => {
+                let filename = filename.or_else(|| {
+                    path.file_name()
+                        .map(|filename| filename.to_string_lossy().into_owned())
+                });
+                self.add_file_part(name, path, content_type, filename)?
+            }
+            CurlValue::Content(ref path) if stdin(path) => self.add_source(
+                name,
+                ReaderSource::new(io::stdin(), None),
+                filename,
+                content_type.or(Some(mime::TEXT_PLAIN)),
+            ),
+            CurlValue::Content(path) => self.add_file_part(
+                name,
+                path,
+                content_type.or(Some(mime::TEXT_PLAIN)),
+                filename,
+            )?,
+        }
+        if let Some(part) = self.parts.last_mut() {
+            for (name, value) in parsed_headers {
+                part.add_header(name, value);
+            }
+        }
+        Ok(())
+    }
+
+    /// Requests a checksum of the data of the part at `index`.
+    ///
+    /// Text, in-memory and replayable parts are hashed right away, and
+    /// their checksum is sent as a `Content-MD5` or `Content-Digest` header
+    /// of the part. Parts read from a plain reader are hashed while they
+    /// are streamed, and their checksum is available from
+    /// [`Body::checksums`](struct.Body.html#method.checksums) once they
+    /// have been sent.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[cfg(feature = "md5")]
+    /// # fn main() {
+    /// use multipart_rfc7578::{Algorithm, Form};
+    ///
+    /// let mut form = Form::default();
+    ///
+    /// form.add_file("file", file!()).expect("file to exist");
+    /// form.set_checksum(0, Algorithm::Md5).expect("file to be readable");
+    /// # }
+    /// # #[cfg(not(feature = "md5"))]
+    /// # fn main() {
+    /// # }
+    /// ```
+    ///
+    #[cfg(any(
+        feature = "md5",
+        feature = "sha1",
+        feature = "sha256",
+        feature = "crc32c"
+    ))]
+    #[inline]
+    pub fn set_checksum(&mut self, index: usize, algorithm: Algorithm) -> Result<(), Error> {
+        self.parts[index].set_checksum(index, algorithm)
+    }
+
+    /// Compresses the data of the part at `index`, and announces it with
+    /// a `Content-Encoding` header of the part.
+    ///
+    /// Text and in-memory parts are compressed right away, so the content
+    /// length of the form stays known. Other parts are compressed while
+    /// they are streamed, and the content length of the form is `None`.
+    /// Checksums cover the compressed data.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[cfg(feature = "gzip")]
+    /// # fn main() {
+    /// use multipart_rfc7578::{Encoding, Form};
+    ///
+    /// let mut form = Form::default();
+    ///
+    /// form.add_text("export", "id,name\n".repeat(1000));
+    /// form.set_content_encoding(0, Encoding::Gzip).expect("text to compress");
+    /// assert!(form.content_length().unwrap() < 8000);
+    /// # }
+    /// # #[cfg(not(feature = "gzip"))]
+    /// # fn main() {
+    /// # }
+    /// ```
+    ///
+    #[cfg(any(
+        feature = "gzip",
+        feature = "deflate",
+        feature = "brotli",
+        feature = "zstd"
+    ))]
+    #[inline]
+    pub fn set_content_encoding(&mut self, index: usize, encoding: Encoding) -> Result<(), Error> {
+        self.parts[index].set_encoding(index, encoding)
+    }
+
+    /// Returns the number of parts.
+    ///
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.parts.len()
+    }
+
+    /// Returns true if the form has no parts.
+    ///
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.parts.is_empty()
+    }
+
+    /// Returns a view of the part at `index`.
+    ///
+    #[inline]
+    pub fn part(&self, index: usize) -> Option<PartInfo<'_>> {
+        self.parts.get(index).map(Part::info)
+    }
+
+    /// Iterates over views of the parts, in the order they are written
+    /// out.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multipart_rfc7578::{Form, PartKind};
+    ///
+    /// let mut form = Form::default();
+    ///
+    /// form.add_text("text", "Hello World!");
+    /// form.add_file("file", file!()).expect("file to exist");
+    ///
+    /// let files: Vec<_> = form
+    ///     .parts()
+    ///     .filter(|part| part.kind() == PartKind::File)
+    ///     .map(|part| part.name())
+    ///     .collect();
+    /// assert_eq!(vec!["file"], files);
+    /// ```
+    ///
+    #[inline]
+    pub fn parts(&self) -> Box<dyn Iterator<Item = PartInfo<'_>> + '_> {
+        Box::new(self.parts.iter().map(Part::info))
+    }
+
+    /// Removes the part at `index` and returns it, so it can be inserted
+    /// again with [`Form::insert`](#method.insert).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multipart_rfc7578::Form;
+    ///
+    /// let mut form = Form::default();
+    ///
+    /// form.add_text("text", "Hello World!");
+    /// form.add_text("token", "secret");
+    /// let token = form.remove(1);
+    /// form.insert(0, token);
+    /// assert_eq!(Some("token"), form.part(0).map(|part| part.name()));
+    /// ```
+    ///
+    #[inline]
+    pub fn remove(&mut self, index: usize) -> OwnedPart<'a> {
+        OwnedPart(self.parts.remove(index))
+    }
+
+    /// Inserts a part removed from this or another form at `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is greater than the number of parts.
+    ///
+    #[inline]
+    pub fn insert(&mut self, index: usize, part: OwnedPart<'a>) {
+        assert!(
+            index <= self.parts.len(),
+            "insertion index is out of bounds"
+        );
+        self.parts.insert(index, part.0);
+    }
+
+    /// Keeps only the parts for which `f` returns true.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multipart_rfc7578::Form;
+    ///
+    /// let mut form = Form::default();
+    ///
+    /// form.add_text("token", "secret");
+    /// form.add_text("text", "Hello World!");
+    /// form.retain(|part| part.name() != "token");
+    /// assert_eq!(1, form.len());
+    /// ```
+    ///
+    #[inline]
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&PartInfo) -> bool,
+    {
+        self.parts.retain(|part| f(&part.info()));
+    }
+
+    /// Inserts the parts of `other` at `index`, keeping their order. The
+    /// boundary, settings and observers of `other` are dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is greater than the number of parts.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multipart_rfc7578::Form;
+    ///
+    /// let mut form = Form::default();
+    /// let mut auth = Form::default();
+    ///
+    /// form.add_text("text", "Hello World!");
+    /// auth.add_text("token", "secret");
+    /// form.insert_at(0, auth);
+    /// assert_eq!(Some("token"), form.part(0).map(|part| part.name()));
+    /// ```
+    ///
+    pub fn insert_at(&mut self, index: usize, other: Form<'a>) {
+        assert!(
+            index <= self.parts.len(),
+            "insertion index is out of bounds"
+        );
+        self.parts.splice(index..index, other.parts);
+    }
+
+    /// Sorts the parts with a comparator. The sort is stable, so parts
+    /// comparing equal keep their order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multipart_rfc7578::{Form, PartKind};
+    ///
+    /// let mut form = Form::default();
+    ///
+    /// form.add_file("file", file!()).expect("file to exist");
+    /// form.add_text("key", "uploads/file.rs");
+    ///
+    /// // Moves files last, as S3 POST uploads require.
+    /// form.sort_by(|a, b| (a.kind() == PartKind::File).cmp(&(b.kind() == PartKind::File)));
+    /// assert_eq!(Some("file"), form.part(1).map(|part| part.name()));
+    /// ```
+    ///
+    #[inline]
+    pub fn sort_by<F>(&mut self, mut compare: F)
+    where
+        F: FnMut(&PartInfo, &PartInfo) -> Ordering,
+    {
+        self.parts.sort_by(|a, b| compare(&a.info(), &b.info()));
+    }
+
+    /// Sets what happens when a part read from a reader, file or factory
+    /// does not produce exactly its declared length. By default, reading
+    /// the form fails with an
+    /// [`Error::LengthMismatch`](enum.Error.html#variant.LengthMismatch)
+    /// naming the part.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multipart_rfc7578::{Form, LengthPolicy};
+    /// use std::io::{Cursor, Read};
+    ///
+    /// let mut form = Form::default();
+    ///
+    /// form.add_reader2("input", Cursor::new("Hello"), None::<String>, None, Some(10));
+    /// form.set_length_policy(LengthPolicy::Adjust);
+    /// form.into_reader().read_to_end(&mut Vec::new()).unwrap();
+    /// ```
+    ///
+    #[inline]
+    pub fn set_length_policy(&mut self, policy: LengthPolicy) {
+        self.length_policy = policy;
+    }
+
+    /// Sets whether a file part fails with an
+    /// [`Error::FileChanged`](enum.Error.html#variant.FileChanged) when
+    /// its size differs from the size it had when it was added. It applies
+    /// to sources with a [`path`](trait.PartSource.html#method.path). The
+    /// check is done before the file is opened and any of its data is
+    /// written out, and the error is reported as the source of an
+    /// [`Error::Read`](enum.Error.html#variant.Read) naming the part.
+    ///
+    /// Otherwise, a file that changed size is handled by the
+    /// [`LengthPolicy`](enum.LengthPolicy.html) once it has been read.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multipart_rfc7578::Form;
+    ///
+    /// let mut form = Form::default();
+    ///
+    /// form.add_file("file", file!()).expect("file to exist");
+    /// form.set_detect_file_changes(true);
+    /// ```
+    ///
+    #[inline]
+    pub fn set_detect_file_changes(&mut self, detect: bool) {
+        self.detect_file_changes = detect;
+    }
+
+    /// Sets which parts carry a `Content-Length` header. The content
+    /// length of the form accounts for the headers.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multipart_rfc7578::{Form, PartContentLength};
+    ///
+    /// let mut form = Form::default();
+    ///
+    /// form.add_text("text", "Hello World!");
+    /// form.add_file("file", file!()).expect("file to exist");
+    /// form.set_part_content_length(PartContentLength::PerPart);
+    /// form.set_content_length_header(1, true);
+    /// ```
+    ///
+    #[inline]
+    pub fn set_part_content_length(&mut self, mode: PartContentLength) {
+        self.part_content_length = mode;
+    }
+
+    /// Selects whether the part at `index` carries a `Content-Length`
+    /// header, if its length is known. This only has an effect with
+    /// [`PartContentLength::PerPart`](enum.PartContentLength.html#variant.PerPart).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    ///
+    #[inline]
+    pub fn set_content_length_header(&mut self, index: usize, send: bool) {
+        self.parts[index].set_content_length_header(send);
+    }
+
+    /// Sets how the headers of the form are formatted. The boundary of the
+    /// form is kept, see
+    /// [`Form::with_profile`](#method.with_profile) to also generate it
+    /// like the client.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multipart_rfc7578::{CurlGenerator, Form, Profile};
+    ///
+    /// let mut form = Form::new::<CurlGenerator>();
+    ///
+    /// form.add_text("text", "Hello World!");
+    /// form.set_profile(Profile::curl());
+    /// assert!(!form.content_type().contains('"'));
+    /// ```
+    ///
+    #[inline]
+    pub fn set_profile(&mut self, profile: Profile) {
+        self.profile = profile;
+    }
+
+    /// Adds an observer that is notified of the progress of the form
+    /// while it is written out.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multipart_rfc7578::{Event, Form, Progress};
+    ///
+    /// let mut form = Form::default();
+    ///
+    /// form.add_text("text", "Hello World!");
+    /// form.add_observer(|event: &Event| println!("{:?}", event));
+    /// form.add_observer(Progress::new(|sent, total| println!("{}/{:?}", sent, total)));
+    /// ```
+    ///
+    #[inline]
+    pub fn add_observer<O>(&mut self, observer: O)
+    where
+        O: Observer + Send + 'a,
+    {
+        self.observers.push(observer);
+    }
+
+    /// Returns a channel receiving the events of the form while it is
+    /// written out.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multipart_rfc7578::{Event, Form};
+    /// use std::io::Read;
+    ///
+    /// let mut form = Form::default();
+    ///
+    /// form.add_text("text", "Hello World!");
+    /// let events = form.events();
+    /// form.into_reader().read_to_end(&mut Vec::new()).unwrap();
+    /// assert_eq!(Some(Event::FormFinished), events.try_iter().last());
+    /// ```
+    ///
+    pub fn events(&mut self) -> mpsc::Receiver<Event> {
+        let (sender, receiver) = mpsc::channel();
+        self.add_observer(sender);
+        receiver
+    }
+
+    /// Returns a stream of the events of the form while it is written out.
+    ///
+    #[cfg(feature = "futures")]
+    pub fn event_stream(&mut self) -> futures::sync::mpsc::UnboundedReceiver<Event> {
+        let (sender, receiver) = futures::sync::mpsc::unbounded();
+        self.add_observer(sender);
+        receiver
+    }
+
+    /// Creates a copy of the form that writes out the same bytes, with the
+    /// same boundary, for example to retry a request or follow a redirect.
+    ///
+    /// Text, in-memory, file, seekable and factory parts can be replayed.
+    /// Parts added with a plain reader can only be read once, and make
+    /// this fail. Seekable readers are shared with the copy, so only one
+    /// of the forms should be written out at a time. Observers are shared
+    /// as well.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multipart_rfc7578::Form;
+    /// use std::io::{Cursor, Read};
+    ///
+    /// let mut form = Form::default();
+    ///
+    /// form.add_text("text", "Hello World!");
+    /// form.add_bytes("bytes", &b"Hello Universe!"[..]);
+    /// let retry = form.try_clone().unwrap();
+    ///
+    /// form.add_reader("input", Cursor::new("not replayable"));
+    /// assert!(form.try_clone().is_err());
+    /// ```
+    ///
+    pub fn try_clone(&self) -> Result<Form<'a>, Error> {
+        let parts = self
+            .parts
+            .iter()
+            .enumerate()
+            .map(|(index, part)| {
+                part.try_clone().ok_or_else(|| Error::NotReplayable {
+                    index,
+                    name: part.name().to_string(),
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Form {
+            parts,
+            boundary: self.boundary.clone(),
+            observers: self.observers.clone(),
+            #[cfg(any(
+                feature = "md5",
+                feature = "sha1",
+                feature = "sha256",
+                feature = "crc32c"
+            ))]
+            checksums: Checksums::default(),
+            length_policy: self.length_policy,
+            part_content_length: self.part_content_length,
+            profile: self.profile.clone(),
+            detect_file_changes: self.detect_file_changes,
+        })
+    }
+
+    /// Creates a fresh `Body` from a copy of the form, leaving the form
+    /// itself ready to be replayed again.
+    ///
+    /// See [`Form::try_clone`](#method.try_clone) for which parts can be
+    /// replayed.
+    ///
+    #[cfg(feature = "futures")]
+    #[inline]
+    pub fn rewind(&self) -> Result<Body<'a>, Error> {
+        self.try_clone().map(Body::from)
+    }
+
+    /// Computes the digest of the exact bytes the form writes out, by
+    /// reading a copy of it. The form itself can be sent afterwards.
+    ///
+    /// Every part must be replayable, see
+    /// [`Form::try_clone`](#method.try_clone). To compute the digest while
+    /// the form is sent instead, use
+    /// [`BodyBuilder::digest`](struct.BodyBuilder.html#method.digest).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[cfg(feature = "sha256")]
+    /// # fn main() {
+    /// use multipart_rfc7578::{Algorithm, Form};
+    ///
+    /// let mut form = Form::default();
+    ///
+    /// form.add_text("text", "Hello World!");
+    /// let digest = form.digest(Algorithm::Sha256).unwrap();
+    /// assert_eq!(32, digest.len());
+    /// # }
+    /// # #[cfg(not(feature = "sha256"))]
+    /// # fn main() {
+    /// # }
+    /// ```
+    ///
+    #[cfg(any(
+        feature = "md5",
+        feature = "sha1",
+        feature = "sha256",
+        feature = "crc32c"
+    ))]
+    #[inline]
+    pub fn digest(&self, algorithm: Algorithm) -> Result<Vec<u8>, Error> {
+        self.consume_copy(Hasher::new(algorithm))
+    }
+
+    /// Computes the value of a `Content-Digest` header for the form, such
+    /// as `sha-256=:...:`.
+    ///
+    /// [See RFC 9530](https://www.rfc-editor.org/rfc/rfc9530#section-2).
+    ///
+    #[cfg(any(
+        feature = "md5",
+        feature = "sha1",
+        feature = "sha256",
+        feature = "crc32c"
+    ))]
+    #[inline]
+    pub fn content_digest(&self, algorithm: Algorithm) -> Result<String, Error> {
+        self.digest(algorithm)
+            .map(|value| checksum::content_digest(algorithm, &value))
+    }
+
+    /// Computes the HMAC-SHA256 of the exact bytes the form writes out, by
+    /// reading a copy of it.
+    ///
+    #[cfg(feature = "hmac")]
+    #[inline]
+    pub fn hmac_sha256(&self, key: &[u8]) -> Result<Vec<u8>, Error> {
+        self.consume_copy(Hasher::hmac_sha256(key))
+    }
+
+    /// Signs the form for webhook-style APIs, returning `sha256=` followed
+    /// by the hexadecimal HMAC-SHA256 of the body, as sent in headers such
+    /// as `X-Hub-Signature-256`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[cfg(feature = "hmac")]
+    /// # fn main() {
+    /// use multipart_rfc7578::Form;
+    ///
+    /// let mut form = Form::default();
+    ///
+    /// form.add_text("text", "Hello World!");
+    /// let signature = form.webhook_signature(b"secret").unwrap();
+    /// assert!(signature.starts_with("sha256="));
+    /// # }
+    /// # #[cfg(not(feature = "hmac"))]
+    /// # fn main() {
+    /// # }
+    /// ```
+    ///
+    #[cfg(feature = "hmac")]
+    #[inline]
+    pub fn webhook_signature(&self, key: &[u8]) -> Result<String, Error> {
+        self.hmac_sha256(key)
+            .map(|mac| format!("sha256={}", checksum::to_hex(&mac)))
+    }
+
+    /// Hashes a copy of the form, without notifying observers.
+    ///
+    #[cfg(any(
+        feature = "md5",
+        feature = "sha1",
+        feature = "sha256",
+        feature = "crc32c"
+    ))]
+    fn consume_copy(&self, hasher: Hasher) -> Result<Vec<u8>, Error> {
+        let mut form = self.try_clone()?;
+        form.observers = Observers::default();
+        hasher.consume(form.into_reader()).map_err(Error::from)
+    }
+
+    /// Renders the form for logging. Boundaries and headers are shown as
+    /// they are written out, text fields up to a limit, and other data as
+    /// a placeholder such as `<file report.pdf, 1.2 MiB>`. No data is read.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multipart_rfc7578::{Form, PreviewOptions};
+    ///
+    /// let mut form = Form::default();
+    ///
+    /// form.add_text("user", "ferris");
+    /// form.add_text("password", "hunter2");
+    /// form.add_bytes_file("file", vec![0; 2048], "data.bin");
+    ///
+    /// let preview = form.preview(&PreviewOptions::default().redact("password"));
+    /// assert!(preview.contains("ferris"));
+    /// assert!(!preview.contains("hunter2"));
+    /// assert!(preview.contains("<bytes data.bin, 2.0 KiB>"));
+    /// ```
+    ///
+    pub fn preview(&self, options: &PreviewOptions) -> String {
+        let mut preview = String::new();
+        for part in &self.parts {
+            preview.push_str(&self.boundary_string());
+            preview.push_str(&part.headers_string(self.part_content_length, &self.profile));
+            preview.push_str(&options.body(part));
+            preview.push_str(CRLF);
+        }
+        preview.push_str(&self.final_boundary_string());
+        preview
+    }
+
+    /// Returns the arguments of a `curl` command sending the same form.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use multipart_rfc7578::Form;
+    ///
+    /// let mut form = Form::default();
+    ///
+    /// form.add_text("text", "Hello World!");
+    /// form.add_file("file", "Cargo.toml").expect("file to exist");
+    ///
+    /// let curl = form.to_curl();
+    /// assert!(!curl.reads_stdin());
+    /// println!("{}", curl.to_shell("http://localhost:80/upload"));
+    /// ```
+    ///
+    #[inline]
+    pub fn to_curl(&self) -> CurlCommand {
+        CurlCommand::new(&self.parts, self.content_type(), &self.profile)
+    }
+
+    /// Returns the `postData` object of a HAR entry for the form, as JSON.
+    ///
+    /// [See](http://www.softwareishard.com/blog/har-12-spec/#postData).
+    ///
+    #[inline]
+    pub fn to_har_post_data(&self) -> String {
+        export::har_post_data(&self.parts, self.content_type())
+    }
+
+    /// Returns the checksums recorded while the form is written out.
+    ///
+    #[cfg(all(
+        feature = "futures",
+        any(
+            feature = "md5",
+            feature = "sha1",
+            feature = "sha256",
+            feature = "crc32c"
+        )
+    ))]
+    #[inline]
+    pub(crate) fn checksums(&self) -> Checksums {
+        self.checksums.clone()
+    }
+
+    /// get boundary as content type string
+    #[inline]
+    pub fn content_type(&self) -> String {
+        if self.profile.quotes_boundary() {
+            format!("multipart/form-data; boundary=\"{}\"", &self.boundary)
+        } else {
+            format!("multipart/form-data; boundary={}", &self.boundary)
+        }
+    }
+
+    #[inline]
+    fn boundary_string(&self) -> String {
+        format!("--{}{}", self.boundary, CRLF)
+    }
+
+    #[inline]
+    fn final_boundary_string(&self) -> String {
+        format!("--{}--{}", self.boundary, CRLF)
+    }
+
+    #[doc(hidden)]
+    pub fn into_reader(self) -> impl Read + 'a {
+        let boundary = Cursor::new(self.boundary_string());
+        let final_boundary = Cursor::new(self.final_boundary_string());
+        let content_length = self.content_length();
+        let options = WriteOptions {
+            #[cfg(any(
+                feature = "md5",
+                feature = "sha1",
+                feature = "sha256",
+                feature = "crc32c"
+            ))]
+            checksums: self.checksums,
+            length_policy: self.length_policy,
+            part_content_length: self.part_content_length,
+            profile: self.profile,
+            detect_file_changes: self.detect_file_changes,
+        };
+        let readers = self
+            .parts
+            .into_iter()
+            .enumerate()
+            .map(move |(index, part)| {
+                (part.started_event(index), part.into_reader(index, &options))
+            });
+        FormReader::new(
+            boundary,
+            readers,
+            final_boundary,
+            content_length,
+            self.observers,
+        )
+    }
+
+    #[inline]
+    fn boundary_len(&self) -> u64 {
+        (self.boundary.len() + 4) as u64
+    }
+
+    /// get content length
+    pub fn content_length(&self) -> Option<u64> {
+        let boundary_len = self.boundary_len() + 2;
+        self.parts.iter().try_fold(boundary_len, |sum, part| {
+            part.content_length(self.part_content_length, &self.profile)
+                .map(|len| sum + len + boundary_len)
+        })
+    }
+}
+
+impl Form<'static> {
+    /// Just for documentation.
+    /// Updates a request instance with the multipart Content-Type header
+    /// and the payload data.
+    ///
+    /// # awc example
+    ///
+    /// ```
+    /// # #[cfg(feature = "awc")]
+    /// use awc::Client;
+    /// use multipart_rfc7578::Form;
+    ///
+    /// # #[cfg(feature = "awc")]
+    /// # fn main() {
+    /// let url = "http://localhost:80/upload";
+    /// let req = Client::default().post(url);
+    /// let mut form = Form::default();
+    ///
+    /// form.add_text("text", "Hello World!");
+    /// let req = form.set_body(req).unwrap();
+    /// # }
+    /// # #[cfg(not(feature = "awc"))]
+    /// # fn main() {
+    /// # }
+    /// ```
+    ///
+    /// # Hyper example
+    ///
     /// ```
     /// # #[cfg(feature = "hyper")]
     /// use hyper::{Method, Request, Uri};
-    /// use multipart_rfc7578::{Form, SetBody};
+    /// use multipart_rfc7578::Form;
     ///
     /// # #[cfg(feature = "hyper")]
     /// # fn main() {
@@ -443,7 +1895,35 @@ impl Form<'static> {
 #[cfg(test)]
 mod tests {
     use super::Form;
-    use std::io::{Cursor, Read};
+    use crate::{
+        BoundaryGenerator, Error, Event, LengthPolicy, PartContentLength, PartKind, PathSource,
+        PreviewOptions, Profile,
+    };
+    use proptest::{collection::vec, prelude::*};
+    use std::{
+        io::{self, Cursor, Read},
+        path::Path,
+    };
+
+    struct TestGenerator;
+
+    impl BoundaryGenerator for TestGenerator {
+        fn generate_boundary() -> String {
+            "boundary".to_string()
+        }
+    }
+
+    /// Reads a form, checking that its content length matches the bytes
+    /// written out.
+    ///
+    fn read_checked(form: Form) -> Vec<u8> {
+        let content_length = form.content_length();
+        let mut out = vec![];
+        form.into_reader().read_to_end(&mut out).unwrap();
+        assert_eq!(content_length, Some(out.len() as u64));
+        out
+    }
+
     #[test]
     fn test_text_form() {
         let mut form = Form::default();
@@ -506,4 +1986,800 @@ bar\r
         form.into_reader().read_to_string(&mut form_string).unwrap();
         assert_eq!(test_string, form_string);
     }
+
+    #[test]
+    fn test_events() {
+        let mut form = Form::default();
+        form.add_text("hello", "world");
+        form.add_reader2("foo", Cursor::new("bar"), Some("foo.txt"), None, Some(3));
+        let content_length = form.content_length();
+        let events = form.events();
+        let mut reader = form.into_reader();
+        assert!(events.try_recv().is_err());
+        reader.read_to_end(&mut Vec::new()).unwrap();
+        let events: Vec<_> = events.try_iter().collect();
+        let emitted: u64 = events
+            .iter()
+            .map(|event| match *event {
+                Event::BytesEmitted(count) => count,
+                _ => 0,
+            })
+            .sum();
+        let milestones: Vec<_> = events
+            .into_iter()
+            .filter(|event| !matches!(*event, Event::BytesEmitted(_)))
+            .collect();
+        assert_eq!(
+            vec![
+                Event::FormStarted { content_length },
+                Event::PartStarted {
+                    index: 0,
+                    name: "hello".to_string(),
+                    filename: None,
+                    length: Some(5),
+                },
+                Event::PartFinished { index: 0 },
+                Event::PartStarted {
+                    index: 1,
+                    name: "foo".to_string(),
+                    filename: Some("foo.txt".to_string()),
+                    length: Some(3),
+                },
+                Event::PartFinished { index: 1 },
+                Event::FormFinished,
+            ],
+            milestones
+        );
+        assert_eq!(content_length, Some(emitted));
+    }
+
+    #[test]
+    fn test_try_clone() {
+        let mut form = Form::default();
+        form.add_text("text", "Hello World!");
+        form.add_bytes_file("bytes", &b"Hello Universe!"[..], "bytes.txt");
+        form.add_seek_reader("seek", Cursor::new("rewound"), None::<&str>, None, Some(7))
+            .unwrap();
+        form.add_reader_factory(
+            "factory",
+            || Ok(Box::new(Cursor::new("created")) as Box<dyn Read + Send>),
+            None::<&str>,
+            None,
+            Some(7),
+        );
+        form.add_file("file", file!()).unwrap();
+        let first = read_checked(form.try_clone().unwrap());
+        let second = read_checked(form.try_clone().unwrap());
+        assert_eq!(first, second);
+        assert_eq!(first, read_checked(form));
+    }
+
+    #[test]
+    fn test_try_clone_reader() {
+        let mut form = Form::default();
+        form.add_text("text", "Hello World!");
+        form.add_reader("input", Cursor::new("not replayable"));
+        let err = form.try_clone().err().unwrap();
+        assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+        assert!(err.to_string().contains("part 1 (`input`)"));
+    }
+
+    #[cfg(feature = "md5")]
+    #[test]
+    fn test_precomputed_checksum() {
+        let mut form = Form::default();
+        form.add_text("hello", "hello world");
+        form.set_checksum(0, crate::Algorithm::Md5).unwrap();
+        let form_string = String::from_utf8(read_checked(form)).unwrap();
+        assert!(form_string.contains("\r\ncontent-md5: XrY7u+Ae7tCTyyK7j1rNww==\r\n"));
+    }
+
+    #[test]
+    fn test_length_policy() {
+        let form = |policy| {
+            let mut form = Form::default();
+            form.add_reader2(
+                "input",
+                Cursor::new("Hello"),
+                None::<String>,
+                None,
+                Some(10),
+            );
+            form.set_length_policy(policy);
+            form
+        };
+        let err = form(LengthPolicy::Strict)
+            .into_reader()
+            .read_to_end(&mut Vec::new())
+            .err()
+            .unwrap();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+        assert!(err.to_string().contains("part 0 (`input`)"));
+        read_checked(form(LengthPolicy::Adjust));
+    }
+
+    /// A reader that always fails.
+    ///
+    struct Failing;
+
+    impl Read for Failing {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::new(io::ErrorKind::BrokenPipe, "broken"))
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    enum TestPart {
+        Text(String),
+        Bytes(Vec<u8>),
+        Reader(Vec<u8>, bool),
+        Failing,
+    }
+
+    fn test_part() -> impl Strategy<Value = TestPart> {
+        prop_oneof![
+            ".{0,20}".prop_map(TestPart::Text),
+            vec(any::<u8>(), 0..64).prop_map(TestPart::Bytes),
+            (vec(any::<u8>(), 0..64), any::<bool>())
+                .prop_map(|(data, known)| TestPart::Reader(data, known)),
+            Just(TestPart::Failing),
+        ]
+    }
+
+    /// Reads everything using a buffer of `size` bytes.
+    ///
+    fn read_chunked<R: Read>(mut read: R, size: usize) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        let mut buf = vec![0; size];
+        loop {
+            match read.read(&mut buf)? {
+                0 => return Ok(out),
+                n => out.extend_from_slice(&buf[..n]),
+            }
+        }
+    }
+
+    #[test]
+    fn test_empty_form() {
+        let form = Form::default();
+        let final_boundary = form.final_boundary_string();
+        assert_eq!(final_boundary.as_bytes(), &read_checked(form)[..]);
+    }
+
+    proptest! {
+        #[test]
+        fn test_random_forms(parts in vec(test_part(), 0..6), size in 1usize..64) {
+            let mut form = Form::default();
+            for (index, part) in parts.iter().cloned().enumerate() {
+                let name = format!("part{}", index);
+                match part {
+                    TestPart::Text(text) => form.add_text(name, text),
+                    TestPart::Bytes(bytes) => form.add_bytes(name, bytes),
+                    TestPart::Reader(data, known) => {
+                        let len = if known { Some(data.len() as u64) } else { None };
+                        form.add_reader2(name, Cursor::new(data), None::<String>, None, len)
+                    }
+                    TestPart::Failing => form.add_reader(name, Failing),
+                }
+            }
+            let final_boundary = form.final_boundary_string();
+            let content_length = form.content_length();
+            let all_known = parts.iter().all(|part| match *part {
+                TestPart::Reader(_, known) => known,
+                TestPart::Failing => false,
+                _ => true,
+            });
+            prop_assert_eq!(all_known, content_length.is_some());
+
+            let failing = parts
+                .iter()
+                .position(|part| matches!(*part, TestPart::Failing));
+            match (read_chunked(form.into_reader(), size), failing) {
+                (Ok(out), None) => {
+                    prop_assert!(out.ends_with(final_boundary.as_bytes()));
+                    if parts.is_empty() {
+                        prop_assert_eq!(final_boundary.as_bytes(), &out[..]);
+                    }
+                    if let Some(content_length) = content_length {
+                        prop_assert_eq!(content_length, out.len() as u64);
+                    }
+                }
+                (Err(err), Some(failing)) => match Error::from(err) {
+                    Error::Read { index, .. } => prop_assert_eq!(failing, index),
+                    err => prop_assert!(false, "unexpected error: {:?}", err),
+                },
+                (result, failing) => prop_assert!(
+                    false,
+                    "unexpected result {:?} for failing part {:?}",
+                    result.map(|out| out.len()),
+                    failing
+                ),
+            }
+        }
+    }
+
+    #[test]
+    fn test_part_content_length() {
+        let form = |mode| {
+            let mut form = Form::default();
+            form.add_text("hello", "world");
+            form.add_reader("unknown", Cursor::new("length"));
+            form.add_bytes("bytes", &b"bar"[..]);
+            form.set_part_content_length(mode);
+            form.set_content_length_header(2, true);
+            let mut form_string = String::new();
+            form.into_reader().read_to_string(&mut form_string).unwrap();
+            form_string.matches("content-length: ").count()
+        };
+        assert_eq!(0, form(PartContentLength::Never));
+        assert_eq!(2, form(PartContentLength::WhenKnown));
+        assert_eq!(1, form(PartContentLength::PerPart));
+
+        for &mode in &[
+            PartContentLength::Never,
+            PartContentLength::WhenKnown,
+            PartContentLength::PerPart,
+        ] {
+            let mut form = Form::default();
+            form.add_text("hello", "world");
+            form.add_bytes("bytes", &b"bar"[..]);
+            form.set_part_content_length(mode);
+            form.set_content_length_header(0, true);
+            read_checked(form);
+        }
+    }
+
+    #[test]
+    fn test_profile() {
+        let mut form = Form::new::<TestGenerator>();
+        form.add_text("hello", "world");
+        form.add_bytes_file("file", &b"data"[..], "data.bin");
+        form.set_profile(Profile::chrome());
+        form.set_part_content_length(PartContentLength::Never);
+        assert_eq!(
+            "multipart/form-data; boundary=boundary",
+            form.content_type()
+        );
+        let form_string = String::from_utf8(read_checked(form)).unwrap();
+        assert_eq!(
+            "--boundary\r
+Content-Disposition: form-data; name=\"hello\"\r
+\r
+world\r
+--boundary\r
+Content-Disposition: form-data; name=\"file\"; filename=\"data.bin\"\r
+Content-Type: application/octet-stream\r
+\r
+data\r
+--boundary--\r
+",
+            form_string
+        );
+    }
+
+    /// Checks that a form created with `profile` writes out `body`, as
+    /// captured from the client, up to the random part of the boundary.
+    ///
+    fn assert_captured(profile: Profile, prefix: &str, content_type: &str, body: &str) {
+        let mut form = Form::with_profile(profile);
+        form.add_text("hello", "world");
+        form.add_bytes_file("file", &b"data"[..], "data.bin");
+        form.set_part_content_length(PartContentLength::Never);
+        let captured = &content_type["multipart/form-data; boundary=".len()..];
+        let boundary = form.boundary.clone();
+        assert!(boundary.starts_with(prefix));
+        assert_eq!(captured.len(), boundary.len());
+        assert_eq!(
+            content_type,
+            form.content_type().replace(&boundary, captured)
+        );
+        let form_string = String::from_utf8(read_checked(form)).unwrap();
+        assert_eq!(body, form_string.replace(&boundary, captured));
+    }
+
+    #[test]
+    fn test_client_profiles() {
+        assert_captured(
+            Profile::chrome(),
+            "----WebKitFormBoundary",
+            "multipart/form-data; boundary=----WebKitFormBoundaryePkpFF7tjBAqx29L",
+            "------WebKitFormBoundaryePkpFF7tjBAqx29L\r
+Content-Disposition: form-data; name=\"hello\"\r
+\r
+world\r
+------WebKitFormBoundaryePkpFF7tjBAqx29L\r
+Content-Disposition: form-data; name=\"file\"; filename=\"data.bin\"\r
+Content-Type: application/octet-stream\r
+\r
+data\r
+------WebKitFormBoundaryePkpFF7tjBAqx29L--\r
+",
+        );
+        assert_captured(
+            Profile::firefox(),
+            "----geckoformboundary",
+            "multipart/form-data; boundary=----geckoformboundary5d1b7f0e9c2a4b3d8e6f1a0c9b8d7e6f",
+            "------geckoformboundary5d1b7f0e9c2a4b3d8e6f1a0c9b8d7e6f\r
+Content-Disposition: form-data; name=\"hello\"\r
+\r
+world\r
+------geckoformboundary5d1b7f0e9c2a4b3d8e6f1a0c9b8d7e6f\r
+Content-Disposition: form-data; name=\"file\"; filename=\"data.bin\"\r
+Content-Type: application/octet-stream\r
+\r
+data\r
+------geckoformboundary5d1b7f0e9c2a4b3d8e6f1a0c9b8d7e6f--\r
+",
+        );
+        // curl 7.88.1, `curl -F hello=world -F file=@data.bin`.
+        //
+        assert_captured(
+            Profile::curl(),
+            "------------------------",
+            "multipart/form-data; boundary=------------------------fe04e04381d99d26",
+            "--------------------------fe04e04381d99d26\r
+Content-Disposition: form-data; name=\"hello\"\r
+\r
+world\r
+--------------------------fe04e04381d99d26\r
+Content-Disposition: form-data; name=\"file\"; filename=\"data.bin\"\r
+Content-Type: application/octet-stream\r
+\r
+data\r
+--------------------------fe04e04381d99d26--\r
+",
+        );
+    }
+
+    #[test]
+    fn test_editing() {
+        let mut form = Form::new::<TestGenerator>();
+        form.add_text("a", "1");
+        form.add_bytes_file("file", &b"data"[..], "data.bin");
+        form.add_text("b", "2");
+        form.add_reader("c", Cursor::new("3"));
+
+        let info = form.part(1).unwrap();
+        assert_eq!(
+            ("file", Some("data.bin"), Some(4), PartKind::Bytes),
+            (info.name(), info.filename(), info.length(), info.kind())
+        );
+        assert_eq!(&mime::APPLICATION_OCTET_STREAM, info.content_type());
+
+        let names = |form: &Form| {
+            form.parts()
+                .map(|part| part.name().to_string())
+                .collect::<Vec<_>>()
+        };
+        form.sort_by(|a, b| (a.kind() == PartKind::Bytes).cmp(&(b.kind() == PartKind::Bytes)));
+        assert_eq!(vec!["a", "b", "c", "file"], names(&form));
+        let removed = form.remove(0);
+        assert_eq!("a", removed.info().name());
+        form.insert(2, removed);
+        assert_eq!(vec!["b", "c", "a", "file"], names(&form));
+        form.retain(|part| part.kind() != PartKind::Reader);
+        assert_eq!(vec!["b", "a", "file"], names(&form));
+
+        let debug = format!("{:?}", form);
+        assert!(debug.contains("boundary: \"boundary\""));
+        assert!(debug.contains("name: \"file\""));
+        assert!(!debug.contains("data\""));
+    }
+
+    #[test]
+    fn test_preview() {
+        let mut form = Form::new::<TestGenerator>();
+        form.add_text("text", "Hello World!");
+        form.add_text("api_key", "secret");
+        form.add_reader("input", Cursor::new("not replayable"));
+        form.set_part_content_length(PartContentLength::Never);
+        let options = PreviewOptions::default().text_limit(5).redact("API_KEY");
+        assert_eq!(
+            "--boundary\r
+content-disposition: form-data; name=\"text\"\r
+content-type: text/plain\r
+\r
+Hello<7 more bytes>\r
+--boundary\r
+content-disposition: form-data; name=\"api_key\"\r
+content-type: text/plain\r
+\r
+<redacted>\r
+--boundary\r
+content-disposition: form-data; name=\"input\"\r
+content-type: application/octet-stream\r
+\r
+<reader, unknown size>\r
+--boundary--\r
+",
+            form.preview(&options)
+        );
+        let mut form_string = String::new();
+        form.into_reader().read_to_string(&mut form_string).unwrap();
+        assert!(form_string.contains("not replayable"));
+    }
+
+    #[test]
+    fn test_curl() {
+        let mut form = Form::new::<TestGenerator>();
+        form.add_text("text", "Hello World!");
+        form.add_text("at", "@not a file");
+        form.add_file("file", "Cargo.toml").unwrap();
+        assert_eq!(
+            vec![
+                "-F",
+                "text=Hello World!",
+                "--form-string",
+                "at=@not a file",
+                "-F",
+                "file=@Cargo.toml;type=application/octet-stream;filename=Cargo.toml",
+            ],
+            form.to_curl().args()
+        );
+        assert!(!form.to_curl().reads_stdin());
+
+        // Sources added directly are exported like the parts of add_text
+        // and add_file.
+        //
+        let mut sourced = Form::new::<TestGenerator>();
+        sourced.add_source("text", String::from("Hello World!"), None::<&str>, None);
+        sourced.add_source("at", String::from("@not a file"), None::<&str>, None);
+        sourced.add_source::<_, &str, _>(
+            "file",
+            PathSource::new("Cargo.toml").unwrap(),
+            None,
+            None,
+        );
+        assert_eq!(form.to_curl().args(), sourced.to_curl().args());
+        let options = PreviewOptions::default();
+        assert_eq!(form.preview(&options), sourced.preview(&options));
+
+        form.add_curl_spec("id=1;headers=\"X-Id: 1\"").unwrap();
+        form.add_reader("input", Cursor::new("data"));
+        let curl = form.to_curl();
+        assert_eq!(Some(4), curl.stdin_part());
+        assert_eq!(
+            [
+                "-F",
+                "id=1;headers=\"X-Id: 1\"",
+                "-F",
+                "input=<-;type=application/octet-stream",
+            ],
+            &curl.args()[6..]
+        );
+
+        let input = form.remove(4);
+        form.add_bytes_file("bytes", &b"data"[..], "data.bin");
+        assert_eq!(
+            [
+                "-F",
+                "bytes=@-;type=application/octet-stream;filename=data.bin"
+            ],
+            &form.to_curl().args()[8..]
+        );
+
+        form.insert(4, input);
+        let curl = form.to_curl();
+        assert!(curl.reads_stdin());
+        assert_eq!(None, curl.stdin_part());
+        assert_eq!(
+            "curl '-H' 'Content-Type: multipart/form-data; boundary=\"boundary\"' \
+             '--data-binary' '@-' 'http://localhost/'",
+            curl.to_shell("http://localhost/")
+        );
+
+        let mut form = Form::new::<TestGenerator>();
+        form.add_text("quoted", "\"a\" b");
+        assert_eq!(
+            vec!["--form-string", "quoted=\"a\" b"],
+            form.to_curl().args()
+        );
+    }
+
+    #[cfg(all(feature = "gzip", feature = "md5"))]
+    #[test]
+    fn test_curl_headers() {
+        use crate::{Algorithm, Encoding};
+
+        let mut form = Form::new::<TestGenerator>();
+        form.add_text("text", "hello world");
+        form.add_file("file", "Cargo.toml").unwrap();
+        form.set_content_encoding(0, Encoding::Gzip).unwrap();
+        form.set_checksum(1, Algorithm::Md5).unwrap();
+        let curl = form.to_curl();
+        assert_eq!(Some(0), curl.stdin_part());
+        assert_eq!(
+            "text=<-;type=text/plain;headers=\"content-encoding: gzip\"",
+            curl.args()[1]
+        );
+        assert!(curl.args()[3].starts_with(
+            "file=@Cargo.toml;type=application/octet-stream;filename=Cargo.toml;\
+             headers=\"content-md5: "
+        ));
+    }
+
+    #[test]
+    fn test_har_post_data() {
+        let mut form = Form::new::<TestGenerator>();
+        form.add_text("text", "Hello \"World\"");
+        form.add_bytes_file("file", &b"data"[..], "data.bin");
+        form.set_profile(Profile::curl());
+        assert_eq!(
+            "{\"mimeType\":\"multipart/form-data; boundary=boundary\",\"params\":[\
+             {\"name\":\"text\",\"value\":\"Hello \\\"World\\\"\"},\
+             {\"name\":\"file\",\"fileName\":\"data.bin\",\
+             \"contentType\":\"application/octet-stream\"}]}",
+            form.to_har_post_data()
+        );
+    }
+
+    #[test]
+    fn test_curl_spec() {
+        let mut form = Form::new::<TestGenerator>();
+        form.add_curl_spec("text=Hello;headers=\"X-Id: 1\"")
+            .unwrap();
+        form.add_curl_spec("file=@src/lib.rs;type=text/x-rust")
+            .unwrap();
+        form.add_curl_spec("content=<Cargo.toml;filename=\"a;b.toml\"")
+            .unwrap();
+        form.set_part_content_length(PartContentLength::Never);
+
+        let parts: Vec<_> = form
+            .parts()
+            .map(|part| {
+                (
+                    part.name(),
+                    part.filename(),
+                    part.content_type().to_string(),
+                    part.kind(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                ("text", None, "text/plain".to_string(), PartKind::Text),
+                (
+                    "file",
+                    Some("lib.rs"),
+                    "text/x-rust".to_string(),
+                    PartKind::File
+                ),
+                (
+                    "content",
+                    Some("a;b.toml"),
+                    "text/plain".to_string(),
+                    PartKind::File
+                ),
+            ],
+            parts
+        );
+        let form_string = String::from_utf8(read_checked(form)).unwrap();
+        assert!(form_string.contains("content-type: text/plain\r\nX-Id: 1\r\n\r\nHello\r\n"));
+
+        let mut form = Form::default();
+        match form.add_curl_spec("file=@missing.txt") {
+            Err(Error::Open { ref path, .. }) => assert_eq!(Path::new("missing.txt"), path),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        match form.add_curl_spec("text=a;headers=bad") {
+            Err(Error::Spec { position, .. }) => assert_eq!(7, position),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        match form.add_curl_spec("f=hello;headers=\"X-A: 1\r\n\r\n--injected\"") {
+            Err(Error::Spec { position, .. }) => assert_eq!(8, position),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        let path = std::env::temp_dir().join(format!(
+            "multipart-rfc7578-headers-{}.txt",
+            std::process::id()
+        ));
+        std::fs::write(&path, "X-Ok: 1\nX Bad: 2\n").unwrap();
+        let result = form.add_curl_spec(&format!("text=a;headers=@{}", path.display()));
+        std::fs::remove_file(&path).unwrap();
+        match result {
+            Err(Error::Spec { position, .. }) => assert_eq!(7, position),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert!(form.is_empty());
+    }
+
+    #[cfg(feature = "dir")]
+    #[test]
+    fn test_add_dir() {
+        use crate::DirOptions;
+
+        let mut form = Form::new::<TestGenerator>();
+        let options = DirOptions::default().include("bin/*").unwrap();
+        form.add_dir("files", "src", &options).unwrap();
+        let infos: Vec<_> = form.parts().collect();
+        assert_eq!(1, infos.len());
+        assert_eq!(Some("src/bin/multipart.rs"), infos[0].filename());
+        read_checked(form);
+
+        let mut form = Form::default();
+        match form.add_dir("files", "missing", &DirOptions::default()) {
+            Err(Error::Open { .. }) => assert!(form.is_empty()),
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_detect_file_changes() {
+        let path = std::env::temp_dir().join(format!(
+            "multipart-rfc7578-changes-{}.txt",
+            std::process::id()
+        ));
+        std::fs::write(&path, "abc").unwrap();
+        let mut form = Form::default();
+        form.add_file("file", &path).unwrap();
+        form.add_source::<_, &str, _>("source", PathSource::new(&path).unwrap(), None, None);
+        std::fs::write(&path, "abcdef").unwrap();
+
+        let read = |form: &Form| {
+            let mut form = form.try_clone().unwrap();
+            form.set_length_policy(LengthPolicy::Adjust);
+            form.into_reader().read_to_end(&mut Vec::new())
+        };
+        assert!(read(&form).is_ok());
+        form.set_detect_file_changes(true);
+        let file = read(&form);
+        form.remove(0);
+        let source = read(&form);
+        std::fs::remove_file(&path).unwrap();
+        for result in [file, source] {
+            match result.map_err(Error::from) {
+                Err(Error::Read {
+                    index: 0, source, ..
+                }) => match Error::from(source) {
+                    Error::FileChanged {
+                        expected: 3,
+                        actual: 6,
+                        ..
+                    } => (),
+                    err => panic!("unexpected source: {:?}", err),
+                },
+                result => panic!("unexpected result: {:?}", result),
+            }
+        }
+    }
+
+    #[cfg(feature = "archive")]
+    #[test]
+    fn test_add_archive() {
+        use crate::{ArchiveFormat, DirOptions};
+
+        let mut form = Form::new::<TestGenerator>();
+        let options = DirOptions::default().include("*.rs").unwrap();
+        form.add_archive("src", "src/bin", ArchiveFormat::Tar, &options)
+            .unwrap();
+        form.add_archive("zip", "src/bin", ArchiveFormat::ZipDeflated, &options)
+            .unwrap();
+        assert_eq!(
+            Some("bin.tar"),
+            form.part(0).and_then(|part| part.filename())
+        );
+        assert_eq!(
+            Some("bin.zip"),
+            form.part(1).and_then(|part| part.filename())
+        );
+        assert_eq!(None, form.content_length());
+
+        form.remove(1);
+        let first = read_checked(form.try_clone().unwrap());
+        assert_eq!(first, read_checked(form));
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_content_encoding() {
+        use crate::Encoding;
+        use flate2::read::GzDecoder;
+
+        let text = "id,name\n".repeat(100);
+        let mut form = Form::new::<TestGenerator>();
+        form.add_text("text", text.clone());
+        form.add_reader("reader", Cursor::new(text.clone()));
+        form.set_content_encoding(0, Encoding::Gzip).unwrap();
+        form.set_content_encoding(1, Encoding::Gzip).unwrap();
+        assert_eq!(None, form.content_length());
+        assert_eq!(None, form.part(1).and_then(|part| part.length()));
+
+        form.remove(1);
+        let body = read_checked(form);
+
+        // Other headers, like content-length, may follow content-encoding,
+        // so the data starts after the blank line ending the part headers.
+        //
+        let find = |from: usize, needle: &[u8]| {
+            from + body[from..]
+                .windows(needle.len())
+                .position(|window| window == needle)
+                .unwrap()
+                + needle.len()
+        };
+        let start = find(find(0, b"content-encoding: gzip"), b"\r\n\r\n");
+        let mut decoded = String::new();
+        GzDecoder::new(&body[start..])
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(text, decoded);
+    }
+
+    #[cfg(any(feature = "json", feature = "cbor", feature = "msgpack"))]
+    #[test]
+    fn test_add_serialized() {
+        use crate::FormParser;
+        use std::collections::BTreeMap;
+
+        let mut value = BTreeMap::new();
+        value.insert("id", 1);
+        let mut form = Form::new::<TestGenerator>();
+        #[cfg(feature = "json")]
+        {
+            form.add_json("json", &value).unwrap();
+            form.add_json2("charset", &value, Some("a.json"), true)
+                .unwrap();
+        }
+        #[cfg(feature = "cbor")]
+        form.add_cbor("cbor", &value).unwrap();
+        #[cfg(feature = "msgpack")]
+        form.add_msgpack("msgpack", &value).unwrap();
+
+        let content_type = form.content_type();
+        let body = read_checked(form);
+
+        let parts = FormParser::new(&content_type).unwrap().parse_bytes(&body);
+        for part in parts.unwrap() {
+            let content_type = part.content_type().unwrap().to_string();
+            match part.name() {
+                #[cfg(feature = "json")]
+                "json" => {
+                    assert_eq!("application/json", content_type);
+                    assert_eq!(&b"{\"id\":1}"[..], part.data());
+                }
+                #[cfg(feature = "json")]
+                "charset" => {
+                    assert_eq!("application/json; charset=utf-8", content_type);
+                    assert_eq!(Some("a.json"), part.filename());
+                }
+                #[cfg(feature = "cbor")]
+                "cbor" => {
+                    assert_eq!("application/cbor", content_type);
+                    assert_eq!(&[0xa1, 0x62, b'i', b'd', 0x01][..], part.data());
+                }
+                #[cfg(feature = "msgpack")]
+                "msgpack" => {
+                    assert_eq!("application/msgpack", content_type);
+                    assert_eq!(&[0x81, 0xa2, b'i', b'd', 0x01][..], part.data());
+                }
+                name => panic!("unexpected part: {:?}", name),
+            }
+        }
+
+        #[cfg(feature = "json")]
+        {
+            let mut form = Form::default();
+            let mut invalid = BTreeMap::new();
+            invalid.insert((1, 2), 3);
+            match form.add_json("invalid", &invalid) {
+                Err(Error::Serialize { ref name, .. }) if name == "invalid" => {
+                    assert!(form.is_empty())
+                }
+                result => panic!("unexpected result: {:?}", result),
+            }
+        }
+    }
+
+    #[test]
+    fn test_add_seekable() {
+        let mut read = Cursor::new("skip, data");
+        read.set_position(6);
+        let mut form = Form::new::<TestGenerator>();
+        form.add_seekable_file("data", read, "data.txt").unwrap();
+        assert_eq!(Some(4), form.part(0).and_then(|part| part.length()));
+
+        let first = String::from_utf8(read_checked(form.try_clone().unwrap())).unwrap();
+        assert!(first.contains("\r\n\r\ndata\r\n"));
+        assert_eq!(first.as_bytes(), &read_checked(form)[..]);
+    }
 }
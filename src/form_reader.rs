@@ -6,66 +6,159 @@
 // copied, modified, or distributed except according to those terms.
 //
 
+use crate::observer::{Event, Observers};
 use std::io::{self, Cursor, Read};
 
 enum State {
     Boundary,
     Part,
     FinalBoundary,
+    Finished,
 }
 
-pub(crate) struct FormReader<T: Read, R: Iterator<Item = T>> {
+pub(crate) struct FormReader<'a, T: Read, R: Iterator<Item = (Event, T)>> {
     boundary: Cursor<String>,
     current: Option<T>,
+    current_started: Option<Event>,
+    form_started: Option<Event>,
+    index: usize,
     readers: R,
     final_boundary: Cursor<String>,
     state: State,
+    observers: Observers<'a>,
 }
 
-impl<T: Read, R: Iterator<Item = T>> FormReader<T, R> {
+impl<'a, T: Read, R: Iterator<Item = (Event, T)>> FormReader<'a, T, R> {
     pub(crate) fn new(
         boundary: Cursor<String>,
         mut readers: R,
         final_boundary: Cursor<String>,
+        content_length: Option<u64>,
+        observers: Observers<'a>,
     ) -> Self {
+        let (current_started, current) = match readers.next() {
+            Some((started, current)) => (Some(started), Some(current)),
+            None => (None, None),
+        };
         Self {
             boundary,
-            current: readers.next(),
+            current,
+            current_started,
+            form_started: Some(Event::FormStarted { content_length }),
+            index: 0,
             readers,
             final_boundary,
             state: State::Boundary,
+            observers,
         }
     }
-}
 
-impl<T: Read, R: Iterator<Item = T>> Read for FormReader<T, R> {
-    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    /// Notifies observers that the current part started.
+    ///
+    #[inline]
+    fn start_part(&mut self) {
+        if let Some(started) = self.current_started.take() {
+            self.observers.notify(&started);
+        }
+    }
+
+    /// Moves on to the next part, returning true if there is one.
+    ///
+    fn next_part(&mut self) -> bool {
+        self.observers.notify(&Event::PartFinished { index: self.index });
+        match self.readers.next() {
+            Some((started, current)) => {
+                self.index += 1;
+                self.current = Some(current);
+                self.current_started = Some(started);
+                true
+            }
+            None => {
+                self.current = None;
+                false
+            }
+        }
+    }
+
+    fn read_final_boundary(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.final_boundary.read(buf)? {
+            0 => {
+                if let State::FinalBoundary = self.state {
+                    self.state = State::Finished;
+                    self.observers.notify(&Event::FormFinished);
+                }
+                Ok(0)
+            }
+            n => Ok(n),
+        }
+    }
+
+    fn read_state(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         match &self.state {
-            State::Boundary => match self.boundary.read(buf)? {
-                0 => {
-                    self.state = State::Part;
-                    self.current.as_mut().unwrap().read(buf)
+            // A form without parts is written out as its final boundary.
+            //
+            State::Boundary | State::Part if self.current.is_none() => {
+                self.state = State::FinalBoundary;
+                self.read_final_boundary(buf)
+            }
+            State::Boundary => {
+                self.start_part();
+                match self.boundary.read(buf)? {
+                    0 => {
+                        self.state = State::Part;
+                        self.read_state(buf)
+                    }
+                    n => Ok(n),
                 }
-                n => Ok(n),
-            },
-            State::Part => match self.current.as_mut().unwrap().read(buf)? {
+            }
+            State::Part => match self
+                .current
+                .as_mut()
+                .map_or(Ok(0), |current| current.read(buf))?
+            {
                 0 => {
-                    self.current = self.readers.next();
-                    match self.current {
-                        Some(_) => {
-                            self.boundary.set_position(0);
-                            self.state = State::Boundary;
-                            self.boundary.read(buf)
-                        }
-                        None => {
-                            self.state = State::FinalBoundary;
-                            self.final_boundary.read(buf)
-                        }
+                    if self.next_part() {
+                        self.boundary.set_position(0);
+                        self.state = State::Boundary;
+                        self.start_part();
+                        self.boundary.read(buf)
+                    } else {
+                        self.state = State::FinalBoundary;
+                        self.read_final_boundary(buf)
                     }
                 }
                 n => Ok(n),
             },
-            State::FinalBoundary => self.final_boundary.read(buf),
+            State::FinalBoundary | State::Finished => self.read_final_boundary(buf),
+        }
+    }
+}
+
+impl<'a, T: Read, R: Iterator<Item = (Event, T)>> Read for FormReader<'a, T, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // An empty buffer would look like the end of the current part.
+        //
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if let Some(started) = self.form_started.take() {
+            self.observers.notify(&started);
+        }
+        let result = self.read_state(buf);
+        if !self.observers.is_empty() {
+            match result {
+                Ok(0) => (),
+                Ok(n) => self.observers.notify(&Event::BytesEmitted(n as u64)),
+                Err(ref e) => self.observers.notify(&Event::Error {
+                    index: match self.state {
+                        State::Boundary | State::Part => Some(self.index),
+                        State::FinalBoundary | State::Finished => None,
+                    },
+                    kind: e.kind(),
+                    message: e.to_string(),
+                }),
+            }
         }
+        result
     }
 }
@@ -0,0 +1,176 @@
+// Copyright 2018 rust-multipart-rfc7578 Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+//
+
+use crate::error::Error;
+use std::{
+    cmp,
+    io::{self, Read},
+};
+
+/// What to do when a part does not produce as many bytes as its declared
+/// length.
+///
+/// Declared lengths are used for the content length of the form, so a
+/// part that does not match would make the body disagree with its
+/// `Content-Length` header.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LengthPolicy {
+    /// Fails with an [`Error::LengthMismatch`](enum.Error.html#variant.LengthMismatch).
+    ///
+    Strict,
+
+    /// Pads a short part with zeros, and truncates a long part, so the
+    /// body still matches its content length.
+    ///
+    Adjust,
+}
+
+impl Default for LengthPolicy {
+    #[inline]
+    fn default() -> Self {
+        LengthPolicy::Strict
+    }
+}
+
+/// A reader checking that the inner reader produces exactly the declared
+/// number of bytes.
+///
+pub(crate) struct CheckedReader<R> {
+    inner: R,
+    expected: u64,
+    remaining: u64,
+    index: usize,
+    name: String,
+    policy: LengthPolicy,
+
+    /// Set once a short reader is padded.
+    ///
+    padding: bool,
+}
+
+impl<R: Read> CheckedReader<R> {
+    pub(crate) fn new(
+        inner: R,
+        expected: u64,
+        index: usize,
+        name: String,
+        policy: LengthPolicy,
+    ) -> Self {
+        Self {
+            inner,
+            expected,
+            remaining: expected,
+            index,
+            name,
+            policy,
+            padding: false,
+        }
+    }
+
+    fn mismatch(&self, actual: u64) -> io::Error {
+        Error::LengthMismatch {
+            index: self.index,
+            name: self.name.clone(),
+            expected: self.expected,
+            actual,
+        }
+        .into()
+    }
+}
+
+impl<R: Read> Read for CheckedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.remaining == 0 {
+            return match self.policy {
+                LengthPolicy::Adjust => Ok(0),
+                LengthPolicy::Strict => match self.inner.read(&mut [0])? {
+                    0 => Ok(0),
+                    _ => Err(self.mismatch(self.expected + 1)),
+                },
+            };
+        }
+        let max = cmp::min(buf.len() as u64, self.remaining) as usize;
+        let buf = &mut buf[..max];
+        if self.padding {
+            for byte in buf.iter_mut() {
+                *byte = 0;
+            }
+            self.remaining -= max as u64;
+            return Ok(max);
+        }
+        match self.inner.read(buf)? {
+            0 => match self.policy {
+                LengthPolicy::Strict => Err(self.mismatch(self.expected - self.remaining)),
+                LengthPolicy::Adjust => {
+                    self.padding = true;
+                    self.read(buf)
+                }
+            },
+            n => {
+                self.remaining -= n as u64;
+                Ok(n)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CheckedReader, LengthPolicy};
+    use crate::Error;
+    use std::io::Read;
+
+    fn read(data: &[u8], expected: u64, policy: LengthPolicy) -> Result<Vec<u8>, Error> {
+        let mut out = Vec::new();
+        CheckedReader::new(data, expected, 1, "file".to_string(), policy)
+            .read_to_end(&mut out)
+            .map(|_| out)
+            .map_err(Error::from)
+    }
+
+    #[test]
+    fn test_exact() {
+        assert_eq!(
+            b"hello".to_vec(),
+            read(b"hello", 5, LengthPolicy::Strict).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_strict() {
+        match read(b"hell", 5, LengthPolicy::Strict) {
+            Err(Error::LengthMismatch {
+                index: 1,
+                ref name,
+                expected: 5,
+                actual: 4,
+            }) if name == "file" => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        match read(b"hello!", 5, LengthPolicy::Strict) {
+            Err(Error::LengthMismatch { actual: 6, .. }) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_adjust() {
+        assert_eq!(
+            b"hell\0".to_vec(),
+            read(b"hell", 5, LengthPolicy::Adjust).unwrap()
+        );
+        assert_eq!(
+            b"hello".to_vec(),
+            read(b"hello!", 5, LengthPolicy::Adjust).unwrap()
+        );
+    }
+}
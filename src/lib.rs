@@ -29,17 +29,82 @@
 //! # }
 //! ```
 //!
+#[cfg(feature = "archive")]
+mod archive;
 mod boundary_generator;
+mod chunk;
+mod curl;
+#[cfg(feature = "dir")]
+mod dir;
+mod error;
+mod export;
 mod form;
 mod form_reader;
+mod length;
+mod observer;
+mod parser;
 mod part;
+mod preview;
+mod profile;
+mod source;
 
 #[cfg(feature = "futures")]
 mod body;
+#[cfg(any(
+    feature = "md5",
+    feature = "sha1",
+    feature = "sha256",
+    feature = "crc32c"
+))]
+mod checksum;
+#[cfg(any(
+    feature = "gzip",
+    feature = "deflate",
+    feature = "brotli",
+    feature = "zstd"
+))]
+mod encoding;
+#[cfg(feature = "futures")]
+mod throttle;
 
+#[cfg(feature = "archive")]
+pub use crate::archive::ArchiveFormat;
 #[cfg(feature = "futures")]
-pub use crate::body::Body;
-pub use crate::boundary_generator::{BoundaryGenerator, RandomAsciiGenerator};
+pub use crate::body::{Body, BodyBuilder};
+pub use crate::boundary_generator::{
+    BoundaryGenerator, CurlGenerator, GeckoGenerator, RandomAsciiGenerator, WebKitGenerator,
+};
+#[cfg(any(
+    feature = "md5",
+    feature = "sha1",
+    feature = "sha256",
+    feature = "crc32c"
+))]
+pub use crate::checksum::{Algorithm, Checksum};
+pub use crate::chunk::IntoChunk;
+pub use crate::curl::{CurlHeader, CurlSpec, CurlValue};
+#[cfg(feature = "dir")]
+pub use crate::dir::{DirOptions, FilenameStyle};
+#[cfg(any(
+    feature = "gzip",
+    feature = "deflate",
+    feature = "brotli",
+    feature = "zstd"
+))]
+pub use crate::encoding::Encoding;
+pub use crate::error::Error;
+pub use crate::export::CurlCommand;
 pub use crate::form::Form;
+pub use crate::length::LengthPolicy;
+pub use crate::observer::{Event, Observer, Progress};
+pub use crate::parser::{FormParser, ParsedPart};
+pub use crate::part::{OwnedPart, PartContentLength, PartInfo, PartKind};
+pub use crate::preview::PreviewOptions;
+pub use crate::profile::{HeaderCase, Profile};
+pub use crate::source::{PartSource, PathSource, ReaderSource, SeekSource};
+#[cfg(all(feature = "futures", feature = "tokio-timer"))]
+pub use crate::throttle::TokioClock;
+#[cfg(feature = "futures")]
+pub use crate::throttle::{Clock, Delay, RateLimiter};
 
 pub(crate) const CRLF: &str = "\r\n";
@@ -0,0 +1,232 @@
+// Copyright 2018 rust-multipart-rfc7578 Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+//
+
+use std::{
+    io,
+    sync::{mpsc, Arc, Mutex},
+};
+
+/// Something that happened while a form was being written out.
+///
+/// Events are delivered to every [`Observer`](trait.Observer.html) added
+/// to a form, in the order they happen.
+///
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum Event {
+    /// The form started being written out, on the first read of its
+    /// reader. The content length is the same as
+    /// [`Form::content_length`](struct.Form.html#method.content_length).
+    ///
+    FormStarted { content_length: Option<u64> },
+
+    /// A part started being written out, starting with its boundary.
+    ///
+    PartStarted {
+        index: usize,
+        name: String,
+        filename: Option<String>,
+        length: Option<u64>,
+    },
+
+    /// Bytes were written out. Boundaries and headers are included, so
+    /// the sum of these adds up to the content length of the form.
+    ///
+    BytesEmitted(u64),
+
+    /// A part, including its trailing line break, was written out.
+    ///
+    PartFinished { index: usize },
+
+    /// The final boundary was written out.
+    ///
+    FormFinished,
+
+    /// Writing out the form failed. The index is the part being written
+    /// out when the error happened, if any.
+    ///
+    Error {
+        index: Option<usize>,
+        kind: io::ErrorKind,
+        message: String,
+    },
+}
+
+/// Receives the [`Event`](enum.Event.html)s of a form being written out.
+///
+/// Observers are called synchronously from the reader, so they should
+/// not block. Closures taking an `&Event` are observers, and so are the
+/// sending halves of channels.
+///
+/// # Examples
+///
+/// ```
+/// use multipart_rfc7578::{Event, Form};
+/// use std::io::Read;
+///
+/// let mut form = Form::default();
+///
+/// form.add_text("text", "Hello World!");
+/// form.add_observer(|event: &Event| println!("{:?}", event));
+/// form.into_reader().read_to_end(&mut Vec::new()).unwrap();
+/// ```
+///
+pub trait Observer {
+    /// Called for every event.
+    ///
+    fn on_event(&mut self, event: &Event);
+}
+
+impl<F> Observer for F
+where
+    F: FnMut(&Event),
+{
+    #[inline]
+    fn on_event(&mut self, event: &Event) {
+        self(event)
+    }
+}
+
+impl Observer for mpsc::Sender<Event> {
+    /// Sends a copy of the event. Events are dropped once the receiver
+    /// hangs up.
+    ///
+    #[inline]
+    fn on_event(&mut self, event: &Event) {
+        let _ = self.send(event.clone());
+    }
+}
+
+#[cfg(feature = "futures")]
+impl Observer for futures::sync::mpsc::UnboundedSender<Event> {
+    /// Sends a copy of the event. Events are dropped once the receiver
+    /// hangs up.
+    ///
+    #[inline]
+    fn on_event(&mut self, event: &Event) {
+        let _ = self.unbounded_send(event.clone());
+    }
+}
+
+/// An observer that reports how many bytes were sent against the content
+/// length of the form.
+///
+/// The callback receives the number of bytes sent so far, and the total,
+/// if it is known.
+///
+/// # Examples
+///
+/// ```
+/// use multipart_rfc7578::{Form, Progress};
+///
+/// let mut form = Form::default();
+///
+/// form.add_text("text", "Hello World!");
+/// form.add_observer(Progress::new(|sent, total: Option<u64>| {
+///     if let Some(total) = total {
+///         println!("{}%", sent * 100 / total);
+///     }
+/// }));
+/// ```
+///
+pub struct Progress<F> {
+    sent: u64,
+    total: Option<u64>,
+    callback: F,
+}
+
+impl<F> Progress<F>
+where
+    F: FnMut(u64, Option<u64>),
+{
+    /// Creates an observer calling `callback` every time bytes are sent.
+    ///
+    #[inline]
+    pub fn new(callback: F) -> Self {
+        Self {
+            sent: 0,
+            total: None,
+            callback,
+        }
+    }
+}
+
+impl<F> Observer for Progress<F>
+where
+    F: FnMut(u64, Option<u64>),
+{
+    fn on_event(&mut self, event: &Event) {
+        match *event {
+            Event::FormStarted { content_length } => {
+                self.sent = 0;
+                self.total = content_length;
+            }
+            Event::BytesEmitted(count) => {
+                self.sent += count;
+                (self.callback)(self.sent, self.total);
+            }
+            _ => (),
+        }
+    }
+}
+
+/// An observer that can be shared between a form and its clones.
+///
+pub(crate) type SharedObserver<'a> = Arc<Mutex<dyn Observer + Send + 'a>>;
+
+/// Delivers events to the observers of a form.
+///
+#[derive(Clone, Default)]
+pub(crate) struct Observers<'a>(Vec<SharedObserver<'a>>);
+
+impl<'a> Observers<'a> {
+    #[inline]
+    pub(crate) fn push<O>(&mut self, observer: O)
+    where
+        O: Observer + Send + 'a,
+    {
+        self.0.push(Arc::new(Mutex::new(observer)));
+    }
+
+    #[inline]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub(crate) fn notify(&self, event: &Event) {
+        for observer in &self.0 {
+            // A panicking observer should not silence the others.
+            //
+            let mut observer = match observer.lock() {
+                Ok(observer) => observer,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+            observer.on_event(event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Event, Observer, Progress};
+
+    #[test]
+    fn test_progress() {
+        let mut reported = vec![];
+        {
+            let mut progress = Progress::new(|sent, total| reported.push((sent, total)));
+            progress.on_event(&Event::FormStarted {
+                content_length: Some(10),
+            });
+            progress.on_event(&Event::BytesEmitted(4));
+            progress.on_event(&Event::PartFinished { index: 0 });
+            progress.on_event(&Event::BytesEmitted(6));
+        }
+        assert_eq!(vec![(4, Some(10)), (10, Some(10))], reported);
+    }
+}
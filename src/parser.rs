@@ -0,0 +1,426 @@
+// Copyright 2018 rust-multipart-rfc7578 Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+//
+
+use crate::{curl::split_header, error::Error};
+use mime::Mime;
+use std::io::Read;
+
+/// Parses multipart/form-data bodies, such as the ones written out by a
+/// [`Form`](struct.Form.html).
+///
+/// The body is read into memory, and split into parts at the boundary.
+/// Text before the first boundary and after the final one is ignored.
+///
+/// # Examples
+///
+/// ```
+/// use multipart_rfc7578::{Form, FormParser};
+/// use std::io::Read;
+///
+/// let mut form = Form::default();
+/// form.add_text("text", "Hello World!");
+///
+/// let parser = FormParser::new(&form.content_type()).unwrap();
+/// let parts = parser.parse(form.into_reader()).unwrap();
+///
+/// assert_eq!("text", parts[0].name());
+/// assert_eq!(b"Hello World!", parts[0].data());
+/// ```
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FormParser {
+    boundary: String,
+}
+
+impl FormParser {
+    /// Creates a parser for the boundary of a `multipart/form-data`
+    /// content type.
+    ///
+    pub fn new(content_type: &str) -> Result<Self, Error> {
+        let error = || Error::ContentType {
+            content_type: content_type.to_string(),
+        };
+        let mime: Mime = content_type.parse().map_err(|_| error())?;
+        if mime.type_() != mime::MULTIPART {
+            return Err(error());
+        }
+        match mime.get_param(mime::BOUNDARY) {
+            Some(boundary) if !boundary.as_str().is_empty() => {
+                Ok(Self::with_boundary(boundary.as_str()))
+            }
+            _ => Err(error()),
+        }
+    }
+
+    /// Creates a parser for a boundary.
+    ///
+    #[inline]
+    pub fn with_boundary<B>(boundary: B) -> Self
+    where
+        B: Into<String>,
+    {
+        Self {
+            boundary: boundary.into(),
+        }
+    }
+
+    /// Returns the boundary.
+    ///
+    #[inline]
+    pub fn boundary(&self) -> &str {
+        &self.boundary
+    }
+
+    /// Reads a body and parses it.
+    ///
+    pub fn parse<R>(&self, mut read: R) -> Result<Vec<ParsedPart>, Error>
+    where
+        R: Read,
+    {
+        let mut body = vec![];
+        read.read_to_end(&mut body)?;
+        self.parse_bytes(&body)
+    }
+
+    /// Parses a body.
+    ///
+    pub fn parse_bytes(&self, body: &[u8]) -> Result<Vec<ParsedPart>, Error> {
+        let delimiter = format!("--{}", self.boundary);
+        let delimiter = delimiter.as_bytes();
+
+        let mut pos = find_delimiter(body, delimiter).ok_or_else(|| Error::Parse {
+            position: 0,
+            message: "missing boundary".to_string(),
+        })?;
+        let mut parts = vec![];
+        loop {
+            pos += delimiter.len();
+            if body[pos..].starts_with(b"--") {
+                return Ok(parts);
+            }
+            // Transport padding may follow a boundary.
+            //
+            while body.get(pos).is_some_and(|&b| b == b' ' || b == b'\t') {
+                pos += 1;
+            }
+            if !body[pos..].starts_with(b"\r\n") {
+                return Err(parse_error(pos, "expected a line break after the boundary"));
+            }
+            pos += 2;
+
+            let (headers, data_start) = if body[pos..].starts_with(b"\r\n") {
+                (vec![], pos + 2)
+            } else {
+                let end = find(&body[pos..], b"\r\n\r\n")
+                    .ok_or_else(|| parse_error(pos, "unterminated headers"))?;
+                (parse_headers(&body[pos..pos + end], pos)?, pos + end + 4)
+            };
+
+            let mut closing = b"\r\n".to_vec();
+            closing.extend_from_slice(delimiter);
+            let data_len = find(&body[data_start..], &closing)
+                .ok_or_else(|| parse_error(data_start, "missing closing boundary"))?;
+            parts.push(ParsedPart::new(
+                headers,
+                body[data_start..data_start + data_len].to_vec(),
+                pos,
+            )?);
+            pos = data_start + data_len + 2;
+        }
+    }
+}
+
+/// A part of a parsed body.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct ParsedPart {
+    headers: Vec<(String, String)>,
+    name: String,
+    filename: Option<String>,
+    content_type: Option<Mime>,
+    data: Vec<u8>,
+}
+
+impl ParsedPart {
+    /// Validates the headers of a part starting at `position`.
+    ///
+    fn new(headers: Vec<(String, String)>, data: Vec<u8>, position: usize) -> Result<Self, Error> {
+        let disposition = header(&headers, "content-disposition")
+            .ok_or_else(|| parse_error(position, "missing Content-Disposition header"))?;
+        let params = disposition_params(disposition)
+            .ok_or_else(|| parse_error(position, "expected a form-data disposition"))?;
+        let param = |key: &str| {
+            params
+                .iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(key))
+                .map(|(_, v)| v.clone())
+        };
+        let name = param("name").ok_or_else(|| parse_error(position, "missing field name"))?;
+        let filename = param("filename");
+        let content_type = match header(&headers, "content-type") {
+            Some(content_type) => Some(
+                content_type
+                    .parse()
+                    .map_err(|_| parse_error(position, "invalid content type"))?,
+            ),
+            None => None,
+        };
+        Ok(Self {
+            headers,
+            name,
+            filename,
+            content_type,
+            data,
+        })
+    }
+
+    /// Returns the headers, in order.
+    ///
+    #[inline]
+    pub fn headers(&self) -> &[(String, String)] {
+        &self.headers
+    }
+
+    /// Returns the value of the first header named `name`, ignoring case.
+    ///
+    #[inline]
+    pub fn header(&self, name: &str) -> Option<&str> {
+        header(&self.headers, name)
+    }
+
+    /// Returns the name of the field.
+    ///
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the filename, if any.
+    ///
+    #[inline]
+    pub fn filename(&self) -> Option<&str> {
+        self.filename.as_deref()
+    }
+
+    /// Returns the content type from the `Content-Type` header, if any. A
+    /// part without one is `text/plain`.
+    ///
+    /// [See](https://tools.ietf.org/html/rfc7578#section-4.4).
+    ///
+    #[inline]
+    pub fn content_type(&self) -> Option<&Mime> {
+        self.content_type.as_ref()
+    }
+
+    /// Returns the data.
+    ///
+    #[inline]
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns the data, consuming the part.
+    ///
+    #[inline]
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+#[inline]
+fn parse_error(position: usize, message: &str) -> Error {
+    Error::Parse {
+        position,
+        message: message.to_string(),
+    }
+}
+
+#[inline]
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// Finds the first delimiter at the start of the body or of a line.
+///
+fn find_delimiter(body: &[u8], delimiter: &[u8]) -> Option<usize> {
+    let mut start = 0;
+    while let Some(i) = find(&body[start..], delimiter) {
+        let pos = start + i;
+        if pos == 0 || body[..pos].ends_with(b"\r\n") {
+            return Some(pos);
+        }
+        start = pos + 1;
+    }
+    None
+}
+
+#[inline]
+fn header<'h>(headers: &'h [(String, String)], name: &str) -> Option<&'h str> {
+    headers
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.as_str())
+}
+
+/// Parses the header lines of a part starting at `position`. Folded lines
+/// are joined to the previous header.
+///
+fn parse_headers(block: &[u8], position: usize) -> Result<Vec<(String, String)>, Error> {
+    let block =
+        std::str::from_utf8(block).map_err(|_| parse_error(position, "headers are not UTF-8"))?;
+    let mut headers: Vec<(String, String)> = vec![];
+    let mut line_start = position;
+    for line in block.split("\r\n") {
+        if line.starts_with([' ', '\t']) {
+            let (_, value) = headers
+                .last_mut()
+                .ok_or_else(|| parse_error(line_start, "unexpected folded line"))?;
+            value.push(' ');
+            value.push_str(line.trim());
+        } else {
+            headers
+                .push(split_header(line).ok_or_else(|| parse_error(line_start, "invalid header"))?);
+        }
+        line_start += line.len() + 2;
+    }
+    Ok(headers)
+}
+
+/// Parses the parameters of a `form-data` disposition. Values can be
+/// tokens or quoted strings.
+///
+fn disposition_params(disposition: &str) -> Option<Vec<(String, String)>> {
+    let mut rest = disposition.trim_start();
+    let end = rest.find(';').unwrap_or(rest.len());
+    if !rest[..end].trim().eq_ignore_ascii_case("form-data") {
+        return None;
+    }
+    rest = &rest[end..];
+
+    let mut params = vec![];
+    while let Some(after) = rest.strip_prefix(';') {
+        rest = after.trim_start();
+        if rest.is_empty() {
+            break;
+        }
+        let eq = rest.find('=')?;
+        let key = rest[..eq].trim().to_string();
+        rest = rest[eq + 1..].trim_start();
+        let value = if let Some(quoted) = rest.strip_prefix('"') {
+            let mut value = String::new();
+            let mut chars = quoted.char_indices();
+            loop {
+                match chars.next()? {
+                    (_, '\\') => value.push(chars.next()?.1),
+                    (i, '"') => {
+                        rest = quoted[i + 1..].trim_start();
+                        break;
+                    }
+                    (_, c) => value.push(c),
+                }
+            }
+            value
+        } else {
+            let end = rest.find(';').unwrap_or(rest.len());
+            let value = rest[..end].trim().to_string();
+            rest = &rest[end..];
+            value
+        };
+        params.push((key, value));
+    }
+    if rest.is_empty() {
+        Some(params)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{disposition_params, FormParser};
+    use crate::{BoundaryGenerator, Error, Form};
+
+    struct TestGenerator;
+
+    impl BoundaryGenerator for TestGenerator {
+        fn generate_boundary() -> String {
+            "boundary".to_string()
+        }
+    }
+
+    fn error_position(body: &str) -> usize {
+        match FormParser::with_boundary("boundary").parse_bytes(body.as_bytes()) {
+            Err(Error::Parse { position, .. }) => position,
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut form = Form::new::<TestGenerator>();
+        form.add_text("text", "Hello\r\n--boundar");
+        form.add_bytes_file("file", &b"\x00\x01"[..], "a;b.bin");
+        form.add_curl_spec("custom=1;headers=\"X-Id: 2\"").unwrap();
+
+        let parser = FormParser::new(&form.content_type()).unwrap();
+        assert_eq!("boundary", parser.boundary());
+        let parts = parser.parse(form.into_reader()).unwrap();
+        assert_eq!(3, parts.len());
+        assert_eq!(b"Hello\r\n--boundar", parts[0].data());
+        assert_eq!(Some(&mime::TEXT_PLAIN), parts[0].content_type());
+        assert_eq!("file", parts[1].name());
+        assert_eq!(Some("a;b.bin"), parts[1].filename());
+        assert_eq!(b"\x00\x01", parts[1].data());
+        assert_eq!(Some("2"), parts[2].header("x-id"));
+    }
+
+    #[test]
+    fn test_framing() {
+        let body = "preamble\r\n--boundary \r\ncontent-disposition: form-data;\r\n name=a\r\n\r\n1\r\n--boundary--\r\nepilogue";
+        let parts = FormParser::with_boundary("boundary")
+            .parse_bytes(body.as_bytes())
+            .unwrap();
+        assert_eq!("a", parts[0].name());
+        assert_eq!(None, parts[0].content_type());
+        assert_eq!(b"1", parts[0].data());
+    }
+
+    #[test]
+    fn test_errors() {
+        match FormParser::new("text/plain; boundary=a") {
+            Err(Error::ContentType { .. }) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        assert_eq!(0, error_position("boundary"));
+        assert_eq!(10, error_position("--boundaryx"));
+        assert_eq!(12, error_position("--boundary\r\nbad\r\n\r\n"));
+        assert_eq!(
+            12,
+            error_position("--boundary\r\nx: 1\r\n\r\n\r\n--boundary--")
+        );
+        assert_eq!(
+            46,
+            error_position("--boundary\r\ncontent-disposition: form-data\r\n\r\n")
+        );
+    }
+
+    #[test]
+    fn test_disposition_params() {
+        assert_eq!(
+            Some(vec![
+                ("name".to_string(), "a;b".to_string()),
+                ("filename".to_string(), "c\"d".to_string())
+            ]),
+            disposition_params("form-data; name=\"a;b\"; filename=\"c\\\"d\"")
+        );
+        assert_eq!(None, disposition_params("attachment; name=a"));
+        assert_eq!(None, disposition_params("form-data; name=\"a"));
+    }
+}
@@ -7,20 +7,200 @@
 // copied, modified, or distributed except according to those terms.
 //
 #![allow(clippy::borrow_interior_mutable_const)]
-use crate::CRLF;
+#[cfg(any(
+    feature = "md5",
+    feature = "sha1",
+    feature = "sha256",
+    feature = "crc32c"
+))]
+use crate::checksum::{self, Algorithm, Checksum, Checksums, Hasher, HashingReader, PartChecksum};
+use crate::chunk::Unpiped;
+#[cfg(any(
+    feature = "gzip",
+    feature = "deflate",
+    feature = "brotli",
+    feature = "zstd"
+))]
+use crate::encoding::{Encoding, PartEncoding};
+#[cfg(any(
+    feature = "md5",
+    feature = "sha1",
+    feature = "sha256",
+    feature = "crc32c",
+    feature = "gzip",
+    feature = "deflate",
+    feature = "brotli",
+    feature = "zstd"
+))]
+use crate::error::Error;
+use crate::error::PartReader;
+use crate::length::{CheckedReader, LengthPolicy};
+use crate::profile::Profile;
+use crate::source::{LazyReader, PartSource};
+use crate::{observer::Event, CRLF};
 use http::header;
 use mime::{self, Mime};
 use std::{
-    fmt::Display,
+    fmt::{self, Display},
     io::{Cursor, Read},
+    path::Path,
+    sync::Arc,
 };
 
+/// Which parts of a form carry a `Content-Length` header.
+///
+/// RFC 7578 deprecates the header in parts, but some servers need it to
+/// stream uploads. The default is `Never`, or `WhenKnown` with the
+/// `part-content-length` feature.
+///
+/// [See](https://tools.ietf.org/html/rfc7578#section-4.8).
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PartContentLength {
+    /// No part carries the header.
+    ///
+    Never,
+
+    /// Every part whose length is known carries the header.
+    ///
+    WhenKnown,
+
+    /// Only the parts selected with
+    /// [`Form::set_content_length_header`](struct.Form.html#method.set_content_length_header)
+    /// carry the header, if their length is known.
+    ///
+    PerPart,
+}
+
+impl Default for PartContentLength {
+    #[inline]
+    fn default() -> Self {
+        if cfg!(feature = "part-content-length") {
+            PartContentLength::WhenKnown
+        } else {
+            PartContentLength::Never
+        }
+    }
+}
+
+/// Where the data of a part comes from.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PartKind {
+    /// A text field.
+    ///
+    Text,
+
+    /// In-memory bytes.
+    ///
+    Bytes,
+
+    /// A file, read from its path.
+    ///
+    File,
+
+    /// A one-shot reader, and the default for other sources.
+    ///
+    Reader,
+
+    /// A seekable reader, rewound on every replay.
+    ///
+    Seek,
+
+    /// A factory creating a new reader on every replay.
+    ///
+    Factory,
+}
+
+/// A read-only view of a part of a form.
+///
+/// Views do not read the data of the part.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct PartInfo<'p> {
+    name: &'p str,
+    filename: Option<&'p str>,
+    content_type: &'p Mime,
+    length: Option<u64>,
+    kind: PartKind,
+}
+
+impl<'p> PartInfo<'p> {
+    /// Returns the name of the form field.
+    ///
+    #[inline]
+    pub fn name(&self) -> &'p str {
+        self.name
+    }
+
+    /// Returns the filename, if the part is a file.
+    ///
+    #[inline]
+    pub fn filename(&self) -> Option<&'p str> {
+        self.filename
+    }
+
+    /// Returns the content type of the part.
+    ///
+    #[inline]
+    pub fn content_type(&self) -> &'p Mime {
+        self.content_type
+    }
+
+    /// Returns the length of the data, if it is known.
+    ///
+    #[inline]
+    pub fn length(&self) -> Option<u64> {
+        self.length
+    }
+
+    /// Returns where the data comes from.
+    ///
+    #[inline]
+    pub fn kind(&self) -> PartKind {
+        self.kind
+    }
+}
+
+/// A part removed from a form with
+/// [`Form::remove`](struct.Form.html#method.remove). It keeps its data,
+/// headers and settings, and can be put back with
+/// [`Form::insert`](struct.Form.html#method.insert).
+///
+pub struct OwnedPart<'a>(pub(crate) Part<'a>);
+
+impl<'a> OwnedPart<'a> {
+    /// Returns a view of the part.
+    ///
+    #[inline]
+    pub fn info(&self) -> PartInfo<'_> {
+        self.0.info()
+    }
+}
+
+impl<'a> fmt::Debug for OwnedPart<'a> {
+    /// Shows the metadata of the part, without reading its data.
+    ///
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("OwnedPart").field(&self.info()).finish()
+    }
+}
+
 /// One part of a body delimited by a boundary line.
 ///
 /// [See RFC2046 5.1](https://tools.ietf.org/html/rfc2046#section-5.1).
 ///
 pub(crate) struct Part<'a> {
-    inner: Inner<'a>,
+    source: Box<dyn 'a + PartSource<'a>>,
+
+    /// The name of the form field.
+    ///
+    name: String,
+
+    /// The filename, if the part is a file.
+    ///
+    filename: Option<String>,
 
     /// Each part can include a content-type header field. If this
     /// is not specified, it defaults to "text/plain", or
@@ -28,13 +208,47 @@ pub(crate) struct Part<'a> {
     ///
     /// [See](https://tools.ietf.org/html/rfc7578#section-4.4)
     ///
-    content_type: String,
+    content_type: Mime,
+
+    /// Whether the content type is the default `text/plain`, rather than
+    /// one set explicitly.
+    ///
+    default_content_type: bool,
 
     /// Each part must contain a Content-Disposition header field.
     ///
     /// [See](https://tools.ietf.org/html/rfc7578#section-4.2).
     ///
     content_disposition: String,
+
+    /// The checksum of the data, if one was requested.
+    ///
+    #[cfg(any(
+        feature = "md5",
+        feature = "sha1",
+        feature = "sha256",
+        feature = "crc32c"
+    ))]
+    checksum: Option<PartChecksum>,
+
+    /// The compression of the data, if one was requested.
+    ///
+    #[cfg(any(
+        feature = "gzip",
+        feature = "deflate",
+        feature = "brotli",
+        feature = "zstd"
+    ))]
+    encoding: Option<PartEncoding>,
+
+    /// Whether the part carries a `Content-Length` header when the form
+    /// selects them per part.
+    ///
+    content_length_header: bool,
+
+    /// Extra headers, written out after the others.
+    ///
+    headers: Vec<(String, String)>,
 }
 
 impl<'a> Part<'a> {
@@ -47,11 +261,11 @@ impl<'a> Part<'a> {
     /// with the same name parameter.
     ///
     pub(crate) fn new<N, F>(
-        inner: Inner<'a>,
+        source: Box<dyn 'a + PartSource<'a>>,
         name: N,
         mime: Option<Mime>,
         filename: Option<F>,
-    ) -> Part
+    ) -> Part<'a>
     where
         N: Display,
         F: Display,
@@ -61,139 +275,616 @@ impl<'a> Part<'a> {
         //
         // [See 4.2](https://tools.ietf.org/html/rfc7578#section-4.2)
         //
+        let name = name.to_string();
         let mut disposition_params = vec![format!("name=\"{}\"", name)];
 
         // `filename` can be supplied for files, but is totally optional.
         //
         // [See 4.2](https://tools.ietf.org/html/rfc7578#section-4.2)
         //
-        if let Some(filename) = filename {
+        let filename = filename.map(|filename| filename.to_string());
+        if let Some(ref filename) = filename {
             disposition_params.push(format!("filename=\"{}\"", filename));
         }
 
-        let content_type = format!("{}", mime.unwrap_or_else(|| inner.default_content_type()));
+        let default_content_type = mime.is_none() && source.content_type() == mime::TEXT_PLAIN;
+        let content_type = mime.unwrap_or_else(|| source.content_type());
         Part {
-            inner,
+            source,
+            name,
+            filename,
             content_type,
+            default_content_type,
             content_disposition: format!("form-data; {}", disposition_params.join("; ")),
+            #[cfg(any(
+                feature = "md5",
+                feature = "sha1",
+                feature = "sha256",
+                feature = "crc32c"
+            ))]
+            checksum: None,
+            #[cfg(any(
+                feature = "gzip",
+                feature = "deflate",
+                feature = "brotli",
+                feature = "zstd"
+            ))]
+            encoding: None,
+            content_length_header: false,
+            headers: vec![],
         }
     }
 
+    /// Requests a checksum of the data of this part. Data that can be
+    /// replayed is hashed right away, and its checksum sent as a header.
+    /// Otherwise it is hashed while it is streamed.
+    ///
+    /// The checksum covers the data as it is sent, after any compression.
+    ///
+    #[cfg(any(
+        feature = "md5",
+        feature = "sha1",
+        feature = "sha256",
+        feature = "crc32c"
+    ))]
+    pub(crate) fn set_checksum(&mut self, index: usize, algorithm: Algorithm) -> Result<(), Error> {
+        self.checksum = Some(match self.replay() {
+            Some(read) => PartChecksum::Precomputed(
+                algorithm,
+                Hasher::new(algorithm).consume(PartReader::new(read, index, self.name.clone()))?,
+            ),
+            None => PartChecksum::Streaming(algorithm),
+        });
+        Ok(())
+    }
+
+    /// Requests a compression of the data of this part. Text and bytes are
+    /// compressed right away, so their length stays known. Otherwise the
+    /// data is compressed while it is streamed.
+    ///
+    #[cfg(any(
+        feature = "gzip",
+        feature = "deflate",
+        feature = "brotli",
+        feature = "zstd"
+    ))]
+    pub(crate) fn set_encoding(&mut self, index: usize, encoding: Encoding) -> Result<(), Error> {
+        self.encoding = Some(match (self.source.kind(), self.source.replay()) {
+            (PartKind::Text, Some(source)) | (PartKind::Bytes, Some(source)) => {
+                let read = LazyReader::new(source, false);
+                let data = encoding.encode(PartReader::new(read, index, self.name.clone()))?;
+                PartEncoding::Precompressed(encoding, data)
+            }
+            _ => PartEncoding::Streaming(encoding),
+        });
+        // A precomputed checksum has to cover the compressed data.
+        //
+        #[cfg(any(
+            feature = "md5",
+            feature = "sha1",
+            feature = "sha256",
+            feature = "crc32c"
+        ))]
+        if let Some(PartChecksum::Precomputed(algorithm, _)) = self.checksum {
+            self.set_checksum(index, algorithm)?;
+        }
+        Ok(())
+    }
+
+    /// Returns a reader producing the data of the part as it is sent, if
+    /// the part can be replayed.
+    ///
+    #[cfg(any(
+        feature = "md5",
+        feature = "sha1",
+        feature = "sha256",
+        feature = "crc32c"
+    ))]
+    fn replay(&self) -> Option<Box<dyn 'a + Read + Send>> {
+        if let Some(data) = self.precompressed() {
+            return Some(Box::new(Cursor::new(data.clone())));
+        }
+        let read: Box<dyn 'a + Read + Send> =
+            Box::new(LazyReader::new(self.source.replay()?, false));
+        #[cfg(any(
+            feature = "gzip",
+            feature = "deflate",
+            feature = "brotli",
+            feature = "zstd"
+        ))]
+        let read = match self.encoding {
+            Some(PartEncoding::Streaming(encoding)) => encoding.encoder(read),
+            _ => read,
+        };
+        Some(read)
+    }
+
+    /// Returns the length of the data as it is sent, if it is known.
+    ///
     #[inline]
-    fn headers_string(&self) -> String {
-        #[cfg(feature = "part-content-length")]
-        let content_length = match self.inner.len() {
-            Some(len) => format!("{}{}: {}", CRLF, header::CONTENT_LENGTH.as_str(), len),
+    fn len(&self) -> Option<u64> {
+        match self.precompressed() {
+            Some(data) => Some(data.len() as u64),
+            None if self.content_encoding().is_some() => None,
+            None => self.source.length(),
+        }
+    }
+
+    /// Returns the token of the `Content-Encoding` header, if the data is
+    /// compressed.
+    ///
+    #[cfg(any(
+        feature = "gzip",
+        feature = "deflate",
+        feature = "brotli",
+        feature = "zstd"
+    ))]
+    #[inline]
+    fn content_encoding(&self) -> Option<&'static str> {
+        self.encoding.as_ref().map(PartEncoding::token)
+    }
+
+    #[cfg(not(any(
+        feature = "gzip",
+        feature = "deflate",
+        feature = "brotli",
+        feature = "zstd"
+    )))]
+    #[inline]
+    fn content_encoding(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Returns the compressed data, if it was compressed up front.
+    ///
+    #[cfg(any(
+        feature = "gzip",
+        feature = "deflate",
+        feature = "brotli",
+        feature = "zstd"
+    ))]
+    #[inline]
+    fn precompressed(&self) -> Option<&Arc<[u8]>> {
+        match self.encoding {
+            Some(PartEncoding::Precompressed(_, ref data)) => Some(data),
+            _ => None,
+        }
+    }
+
+    #[cfg(not(any(
+        feature = "gzip",
+        feature = "deflate",
+        feature = "brotli",
+        feature = "zstd"
+    )))]
+    #[inline]
+    fn precompressed(&self) -> Option<&Arc<[u8]>> {
+        None
+    }
+
+    /// Returns the header carrying a precomputed checksum.
+    ///
+    #[cfg(any(
+        feature = "md5",
+        feature = "sha1",
+        feature = "sha256",
+        feature = "crc32c"
+    ))]
+    #[inline]
+    fn checksum_header(&self) -> Option<(&'static str, String)> {
+        match self.checksum {
+            Some(PartChecksum::Precomputed(algorithm, ref value)) => {
+                Some(checksum::header(algorithm, value))
+            }
+            _ => None,
+        }
+    }
+
+    #[cfg(not(any(
+        feature = "md5",
+        feature = "sha1",
+        feature = "sha256",
+        feature = "crc32c"
+    )))]
+    #[inline]
+    fn checksum_header(&self) -> Option<(&'static str, String)> {
+        None
+    }
+
+    /// Returns true if the data is hashed while it is streamed.
+    ///
+    #[cfg(any(
+        feature = "md5",
+        feature = "sha1",
+        feature = "sha256",
+        feature = "crc32c"
+    ))]
+    #[inline]
+    fn has_streaming_checksum(&self) -> bool {
+        matches!(self.checksum, Some(PartChecksum::Streaming(_)))
+    }
+
+    #[cfg(not(any(
+        feature = "md5",
+        feature = "sha1",
+        feature = "sha256",
+        feature = "crc32c"
+    )))]
+    #[inline]
+    fn has_streaming_checksum(&self) -> bool {
+        false
+    }
+
+    /// Adds a custom header, which must have been checked with
+    /// [`is_valid_header`](fn.is_valid_header.html).
+    ///
+    #[inline]
+    pub(crate) fn add_header(&mut self, name: String, value: String) {
+        debug_assert!(is_valid_header(&name, &value));
+        self.headers.push((name, value));
+    }
+
+    #[inline]
+    pub(crate) fn set_content_length_header(&mut self, send: bool) {
+        self.content_length_header = send;
+    }
+
+    /// Returns the length sent in a `Content-Length` header, if any.
+    ///
+    #[inline]
+    fn content_length_header(&self, mode: PartContentLength) -> Option<u64> {
+        match mode {
+            PartContentLength::Never => None,
+            PartContentLength::WhenKnown => self.len(),
+            PartContentLength::PerPart if self.content_length_header => self.len(),
+            PartContentLength::PerPart => None,
+        }
+    }
+
+    /// Returns true if the `Content-Type` header is left out.
+    ///
+    #[inline]
+    fn omits_content_type(&self, profile: &Profile) -> bool {
+        self.default_content_type && profile.omits_default_content_type()
+    }
+
+    pub(crate) fn headers_string(&self, mode: PartContentLength, profile: &Profile) -> String {
+        let content_type = if self.omits_content_type(profile) {
+            String::new()
+        } else {
+            format!(
+                "{}{}: {}",
+                CRLF,
+                profile.header_name(header::CONTENT_TYPE.as_str()),
+                self.content_type
+            )
+        };
+        let content_encoding = match self.content_encoding() {
+            Some(token) => format!(
+                "{}{}: {}",
+                CRLF,
+                profile.header_name(header::CONTENT_ENCODING.as_str()),
+                token
+            ),
             None => String::new(),
         };
-        #[cfg(not(feature = "part-content-length"))]
-        let content_length = "";
+        let content_length = match self.content_length_header(mode) {
+            Some(len) => format!(
+                "{}{}: {}",
+                CRLF,
+                profile.header_name(header::CONTENT_LENGTH.as_str()),
+                len
+            ),
+            None => String::new(),
+        };
+        let checksum = match self.checksum_header() {
+            Some((name, value)) => format!("{}{}: {}", CRLF, profile.header_name(name), value),
+            None => String::new(),
+        };
+        let headers: String = self
+            .headers
+            .iter()
+            .map(|(name, value)| format!("{}{}: {}", CRLF, name, value))
+            .collect();
         format!(
-            "{}: {}{}{}: {}{}{}{}",
-            header::CONTENT_DISPOSITION.as_str(),
+            "{}: {}{}{}{}{}{}{}{}",
+            profile.header_name(header::CONTENT_DISPOSITION.as_str()),
             self.content_disposition,
-            CRLF,
-            header::CONTENT_TYPE.as_str(),
-            self.content_type,
+            content_type,
+            content_encoding,
             content_length,
+            checksum,
+            headers,
             CRLF,
             CRLF
         )
     }
 
-    pub(crate) fn into_reader(self) -> impl Read + 'a {
-        let cursor = Cursor::new(self.headers_string());
-        let inner = match self.inner {
-            Inner::Text(string) => Box::new(Cursor::new(string.into_bytes())),
-            Inner::Read(read, _) => read,
-        };
-        cursor.chain(inner).chain(Cursor::new(CRLF))
+    /// Returns the headers written after the content type: the content
+    /// encoding, the checksum and the custom headers.
+    ///
+    pub(crate) fn extra_headers(&self, profile: &Profile) -> Vec<String> {
+        let mut headers = vec![];
+        if let Some(token) = self.content_encoding() {
+            headers.push(format!(
+                "{}: {}",
+                profile.header_name(header::CONTENT_ENCODING.as_str()),
+                token
+            ));
+        }
+        if let Some((name, value)) = self.checksum_header() {
+            headers.push(format!("{}: {}", profile.header_name(name), value));
+        }
+        headers.extend(
+            self.headers
+                .iter()
+                .map(|(name, value)| format!("{}: {}", name, value)),
+        );
+        headers
     }
 
-    #[inline]
-    fn content_disposition_len(&self) -> u64 {
-        (header::CONTENT_DISPOSITION.as_str().len() + 2 + self.content_disposition.len() + 2) as u64
+    /// Returns the event announcing that this part started being written
+    /// out.
+    ///
+    pub(crate) fn started_event(&self, index: usize) -> Event {
+        Event::PartStarted {
+            index,
+            name: self.name.clone(),
+            filename: self.filename.clone(),
+            length: self.len(),
+        }
     }
 
-    #[inline]
-    fn content_type_len(&self) -> u64 {
-        (header::CONTENT_TYPE.as_str().len() + 2 + self.content_type.len() + 2) as u64
+    /// Returns a copy of this part that produces the same bytes, if the
+    /// source can be replayed.
+    ///
+    pub(crate) fn try_clone(&self) -> Option<Part<'a>> {
+        self.source.replay().map(|source| Part {
+            source,
+            name: self.name.clone(),
+            filename: self.filename.clone(),
+            content_type: self.content_type.clone(),
+            default_content_type: self.default_content_type,
+            content_disposition: self.content_disposition.clone(),
+            #[cfg(any(
+                feature = "md5",
+                feature = "sha1",
+                feature = "sha256",
+                feature = "crc32c"
+            ))]
+            checksum: self.checksum.clone(),
+            #[cfg(any(
+                feature = "gzip",
+                feature = "deflate",
+                feature = "brotli",
+                feature = "zstd"
+            ))]
+            encoding: self.encoding.clone(),
+            content_length_header: self.content_length_header,
+            headers: self.headers.clone(),
+        })
     }
 
     #[inline]
-    fn content_length_len(&self) -> u64 {
-        #[cfg(feature = "part-content-length")]
-        return (header::CONTENT_LENGTH.as_str().len()
-            + 2
-            + self.inner.len().unwrap().to_string().len()
-            + 2) as u64;
-        #[cfg(not(feature = "part-content-length"))]
-        0
+    pub(crate) fn name(&self) -> &str {
+        &self.name
     }
 
+    /// Returns the text of a text field, unless it is compressed.
+    ///
     #[inline]
-    pub(crate) fn content_length(&self) -> Option<u64> {
-        self.inner.len().map(|len| {
-            len + self.content_disposition_len()
-                + self.content_length_len()
-                + self.content_type_len()
-                + 2
-        })
+    pub(crate) fn text(&self) -> Option<&str> {
+        self.source
+            .text()
+            .filter(|_| self.content_encoding().is_none())
     }
-}
 
-pub(crate) enum Inner<'a> {
-    /// The `Read` variant captures multiple cases.
+    /// Returns the path of a file part, unless it is compressed.
     ///
-    ///   * The first is it supports uploading a file, which is explicitly
-    ///     described in RFC 7578.
-    ///
-    ///   * The second (which is not described by RFC 7578), is it can handle
-    ///     arbitrary input streams (for example, a server response).
-    ///     Any arbitrary input stream is automatically considered a file,
-    ///     and assigned the corresponding content type if not explicitly
-    ///     specified.
-    ///
-    Read(Box<'a + Read + Send>, Option<u64>),
+    #[inline]
+    pub(crate) fn path(&self) -> Option<&Path> {
+        self.source
+            .path()
+            .filter(|_| self.content_encoding().is_none())
+    }
 
-    /// The `String` variant handles "text/plain" form data payloads.
+    /// Returns a read-only view of this part.
     ///
-    Text(String),
-}
+    pub(crate) fn info(&self) -> PartInfo<'_> {
+        PartInfo {
+            name: &self.name,
+            filename: self.filename.as_deref(),
+            content_type: &self.content_type,
+            length: self.len(),
+            kind: self.source.kind(),
+        }
+    }
 
-impl<'a> Inner<'a> {
-    /// Returns the default content-type header value as described in section 4.4.
-    ///
-    /// [See](https://tools.ietf.org/html/rfc7578#section-4.4)
+    /// Turns the part into a reader. Checksums are recorded in the
+    /// checksums of `options`, either right away if they were precomputed,
+    /// or once the data has been streamed. Parts with a declared length are
+    /// checked against it according to the length policy.
     ///
+    pub(crate) fn into_reader(self, index: usize, options: &WriteOptions) -> impl Read + 'a {
+        let cursor =
+            Cursor::new(self.headers_string(options.part_content_length, &options.profile));
+        let (read, declared_len): (Box<dyn 'a + Read + Send>, _) = match self.precompressed() {
+            Some(data) => (Box::new(Cursor::new(data.clone())), None),
+            None => {
+                // Text and bytes always match their length.
+                //
+                let declared_len = match self.source.kind() {
+                    PartKind::Text | PartKind::Bytes => None,
+                    _ => self.source.length(),
+                };
+                // Readers hashing or compressing the data need to see it,
+                // so chunks cannot bypass them.
+                //
+                let unpiped = self.content_encoding().is_some() || self.has_streaming_checksum();
+                let read = LazyReader::new(self.source, options.detect_file_changes);
+                let read: Box<dyn 'a + Read + Send> = if unpiped {
+                    Box::new(Unpiped(read))
+                } else {
+                    Box::new(read)
+                };
+                (read, declared_len)
+            }
+        };
+        let read = PartReader::new(read, index, self.name.clone());
+        let read: Box<dyn 'a + Read + Send> = match declared_len {
+            Some(len) => Box::new(CheckedReader::new(
+                read,
+                len,
+                index,
+                self.name.clone(),
+                options.length_policy,
+            )),
+            None => Box::new(read),
+        };
+        // The declared length is checked on the data before it is
+        // compressed, and the checksum covers the compressed data.
+        //
+        #[cfg(any(
+            feature = "gzip",
+            feature = "deflate",
+            feature = "brotli",
+            feature = "zstd"
+        ))]
+        let read = match self.encoding {
+            Some(PartEncoding::Streaming(encoding)) => encoding.encoder(read),
+            _ => read,
+        };
+        #[cfg(any(
+            feature = "md5",
+            feature = "sha1",
+            feature = "sha256",
+            feature = "crc32c"
+        ))]
+        let read: Box<dyn 'a + Read + Send> = match self.checksum {
+            Some(PartChecksum::Precomputed(algorithm, value)) => {
+                let checksum = Checksum {
+                    index,
+                    name: self.name,
+                    algorithm,
+                    value,
+                };
+                match options.checksums.lock() {
+                    Ok(mut checksums) => checksums.push(checksum),
+                    Err(poisoned) => poisoned.into_inner().push(checksum),
+                }
+                read
+            }
+            Some(PartChecksum::Streaming(algorithm)) => Box::new(HashingReader::new(
+                read,
+                algorithm,
+                index,
+                self.name,
+                options.checksums.clone(),
+            )),
+            None => read,
+        };
+        cursor.chain(read).chain(Cursor::new(CRLF))
+    }
+
+    #[inline]
+    fn content_disposition_len(&self) -> u64 {
+        (header::CONTENT_DISPOSITION.as_str().len() + 2 + self.content_disposition.len() + 2) as u64
+    }
+
     #[inline]
-    fn default_content_type(&self) -> Mime {
-        match *self {
-            Inner::Read(_, _) => mime::APPLICATION_OCTET_STREAM,
-            Inner::Text(_) => mime::TEXT_PLAIN,
+    fn content_type_len(&self, profile: &Profile) -> u64 {
+        if self.omits_content_type(profile) {
+            0
+        } else {
+            (header::CONTENT_TYPE.as_str().len() + 2 + self.content_type.as_ref().len() + 2) as u64
         }
     }
 
-    /// Returns the length of the inner type.
-    ///
     #[inline]
-    fn len(&self) -> Option<u64> {
-        match *self {
-            Inner::Read(_, len) => len,
-            Inner::Text(ref s) => Some(s.len() as u64),
+    fn content_encoding_len(&self) -> u64 {
+        match self.content_encoding() {
+            Some(token) => (header::CONTENT_ENCODING.as_str().len() + 2 + token.len() + 2) as u64,
+            None => 0,
+        }
+    }
+
+    #[inline]
+    fn content_length_len(&self, mode: PartContentLength) -> u64 {
+        match self.content_length_header(mode) {
+            Some(len) => {
+                (header::CONTENT_LENGTH.as_str().len() + 2 + len.to_string().len() + 2) as u64
+            }
+            None => 0,
+        }
+    }
+
+    #[inline]
+    fn checksum_len(&self) -> u64 {
+        match self.checksum_header() {
+            Some((name, value)) => (name.len() + 2 + value.len() + 2) as u64,
+            None => 0,
         }
     }
+
+    #[inline]
+    fn headers_len(&self) -> u64 {
+        self.headers
+            .iter()
+            .map(|(name, value)| (name.len() + 2 + value.len() + 2) as u64)
+            .sum()
+    }
+
+    #[inline]
+    pub(crate) fn content_length(&self, mode: PartContentLength, profile: &Profile) -> Option<u64> {
+        self.len().map(|len| {
+            len + self.content_disposition_len()
+                + self.content_length_len(mode)
+                + self.content_type_len(profile)
+                + self.content_encoding_len()
+                + self.checksum_len()
+                + self.headers_len()
+                + 2
+        })
+    }
+}
+
+/// Returns true if `name` is a token and `value` does not contain line
+/// breaks, so the header cannot end the headers of a part early.
+///
+/// [See](https://tools.ietf.org/html/rfc7230#section-3.2).
+///
+pub(crate) fn is_valid_header(name: &str, value: &str) -> bool {
+    let is_tchar = |byte: u8| byte.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&byte);
+    !name.is_empty() && name.bytes().all(is_tchar) && !value.contains(['\r', '\n'])
+}
+
+/// The settings of a form that apply to writing out each of its parts.
+///
+#[derive(Clone, Default)]
+pub(crate) struct WriteOptions {
+    #[cfg(any(
+        feature = "md5",
+        feature = "sha1",
+        feature = "sha256",
+        feature = "crc32c"
+    ))]
+    pub(crate) checksums: Checksums,
+    pub(crate) length_policy: LengthPolicy,
+    pub(crate) part_content_length: PartContentLength,
+    pub(crate) profile: Profile,
+    pub(crate) detect_file_changes: bool,
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Inner, Part};
+    use super::{Part, WriteOptions};
+    use crate::ReaderSource;
     use std::io::{Cursor, Read};
     #[test]
     fn test_inner_text() {
         let name = "hello";
         let inner_content = "world";
-        let inner = Inner::Text(inner_content.to_string());
+        let inner = Box::new(inner_content.to_string());
         #[cfg(feature = "part-content-length")]
         let test_string = "content-disposition: form-data; name=\"hello\"\r
 content-type: text/plain\r
@@ -210,7 +901,9 @@ world\r
         let test_string = test_string.to_string();
         let part = Part::new::<_, &str>(inner, name, None, None);
         let mut part_string = String::new();
-        part.into_reader().read_to_string(&mut part_string).unwrap();
+        part.into_reader(0, &WriteOptions::default())
+            .read_to_string(&mut part_string)
+            .unwrap();
         assert_eq!(test_string, part_string);
     }
 
@@ -218,10 +911,10 @@ world\r
     fn test_inner_read() {
         let name = "hello";
         let inner_content = "world";
-        let inner = Inner::Read(
-            Box::new(Cursor::new(inner_content.as_bytes())),
+        let inner = Box::new(ReaderSource::new(
+            Cursor::new(inner_content.as_bytes()),
             Some(inner_content.len() as u64),
-        );
+        ));
         #[cfg(feature = "part-content-length")]
         let test_string = "content-disposition: form-data; name=\"hello\"\r
 content-type: application/octet-stream\r
@@ -238,7 +931,9 @@ world\r
         let test_string = test_string.to_string();
         let part = Part::new::<_, &str>(inner, name, None, None);
         let mut part_string = String::new();
-        part.into_reader().read_to_string(&mut part_string).unwrap();
+        part.into_reader(0, &WriteOptions::default())
+            .read_to_string(&mut part_string)
+            .unwrap();
         assert_eq!(test_string, part_string);
     }
 }
@@ -0,0 +1,164 @@
+// Copyright 2018 rust-multipart-rfc7578 Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+//
+
+use crate::part::{Part, PartKind};
+use std::{fmt, sync::Arc};
+
+/// A rule deciding whether a field is redacted from its name.
+///
+type RedactRule = Arc<dyn Fn(&str) -> bool + Send + Sync>;
+
+/// Configures [`Form::preview`](struct.Form.html#method.preview).
+///
+/// By default, text fields are shown up to 1024 bytes, and no field is
+/// redacted.
+///
+/// # Examples
+///
+/// ```
+/// use multipart_rfc7578::PreviewOptions;
+///
+/// let options = PreviewOptions::default()
+///     .text_limit(64)
+///     .redact("password")
+///     .redact_with(|name| name.ends_with("_token"));
+/// ```
+///
+#[derive(Clone)]
+pub struct PreviewOptions {
+    text_limit: usize,
+    rules: Vec<RedactRule>,
+}
+
+impl Default for PreviewOptions {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            text_limit: 1024,
+            rules: vec![],
+        }
+    }
+}
+
+impl fmt::Debug for PreviewOptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PreviewOptions")
+            .field("text_limit", &self.text_limit)
+            .field("rules", &self.rules.len())
+            .finish()
+    }
+}
+
+impl PreviewOptions {
+    /// Shows at most `limit` bytes of every text field.
+    ///
+    #[inline]
+    pub fn text_limit(mut self, limit: usize) -> Self {
+        self.text_limit = limit;
+        self
+    }
+
+    /// Redacts the data of fields named `name`, ignoring case.
+    ///
+    pub fn redact<N>(self, name: N) -> Self
+    where
+        N: Into<String>,
+    {
+        let name = name.into();
+        self.redact_with(move |field: &str| field.eq_ignore_ascii_case(&name))
+    }
+
+    /// Redacts the data of fields whose name matches `rule`.
+    ///
+    #[inline]
+    pub fn redact_with<F>(mut self, rule: F) -> Self
+    where
+        F: Fn(&str) -> bool + Send + Sync + 'static,
+    {
+        self.rules.push(Arc::new(rule));
+        self
+    }
+
+    #[inline]
+    fn redacts(&self, name: &str) -> bool {
+        self.rules.iter().any(|rule| rule(name))
+    }
+
+    /// Renders the data of a part.
+    ///
+    pub(crate) fn body(&self, part: &Part) -> String {
+        let info = part.info();
+        if self.redacts(info.name()) {
+            return "<redacted>".to_string();
+        }
+        if let Some(text) = part.text() {
+            return truncate(text, self.text_limit);
+        }
+        let kind = match info.kind() {
+            PartKind::Text => "text",
+            PartKind::File => "file",
+            PartKind::Bytes => "bytes",
+            _ => "reader",
+        };
+        let size = match info.length() {
+            Some(len) => human_size(len),
+            None => "unknown size".to_string(),
+        };
+        match info.filename() {
+            Some(filename) => format!("<{} {}, {}>", kind, filename, size),
+            None => format!("<{}, {}>", kind, size),
+        }
+    }
+}
+
+/// Truncates `text` to at most `limit` bytes, on a character boundary.
+///
+fn truncate(text: &str, limit: usize) -> String {
+    if text.len() <= limit {
+        return text.to_string();
+    }
+    let mut end = limit;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}<{} more bytes>", &text[..end], text.len() - end)
+}
+
+/// Formats a number of bytes with a binary unit, such as `1.2 MiB`.
+///
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{human_size, truncate};
+
+    #[test]
+    fn test_human_size() {
+        assert_eq!("4 B", human_size(4));
+        assert_eq!("1.0 KiB", human_size(1024));
+        assert_eq!("1.2 MiB", human_size(1_258_291));
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!("hello", truncate("hello", 5));
+        assert_eq!("h<5 more bytes>", truncate("héllo", 2));
+    }
+}
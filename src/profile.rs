@@ -0,0 +1,206 @@
+// Copyright 2018 rust-multipart-rfc7578 Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+//
+
+use crate::boundary_generator::{
+    BoundaryGenerator, CurlGenerator, GeckoGenerator, RandomAsciiGenerator, WebKitGenerator,
+};
+use std::borrow::Cow;
+
+/// The casing of header names in parts.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HeaderCase {
+    /// Lowercase names, such as `content-disposition`.
+    ///
+    Lower,
+
+    /// Capitalized names, such as `Content-Disposition`.
+    ///
+    Title,
+}
+
+/// Controls how a form formats its headers, for servers that only accept
+/// the output of particular clients.
+///
+/// The default profile writes lowercase header names, quotes the
+/// boundary in the content type, and always writes a `Content-Type`
+/// header. The built-in profiles match browsers and curl, including the
+/// boundaries of forms created with
+/// [`Form::with_profile`](struct.Form.html#method.with_profile).
+///
+/// # Examples
+///
+/// ```
+/// use multipart_rfc7578::{Form, HeaderCase, Profile};
+///
+/// let mut form = Form::with_profile(Profile::chrome());
+///
+/// assert!(form.content_type().contains("----WebKitFormBoundary"));
+/// form.set_profile(Profile::default().header_case(HeaderCase::Title));
+/// ```
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Profile {
+    header_case: HeaderCase,
+    quote_boundary: bool,
+    omit_default_content_type: bool,
+    boundary: BoundaryStyle,
+}
+
+/// The client whose boundaries a profile generates.
+///
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum BoundaryStyle {
+    Random,
+    WebKit,
+    Gecko,
+    Curl,
+}
+
+impl Default for Profile {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            header_case: HeaderCase::Lower,
+            quote_boundary: true,
+            omit_default_content_type: false,
+            boundary: BoundaryStyle::Random,
+        }
+    }
+}
+
+impl Profile {
+    /// Matches Chrome, and other browsers based on WebKit: capitalized
+    /// header names, an unquoted boundary, and no `Content-Type` on text
+    /// fields. Boundaries are generated by
+    /// [`WebKitGenerator`](struct.WebKitGenerator.html).
+    ///
+    #[inline]
+    pub fn chrome() -> Self {
+        Self {
+            header_case: HeaderCase::Title,
+            quote_boundary: false,
+            omit_default_content_type: true,
+            boundary: BoundaryStyle::WebKit,
+        }
+    }
+
+    /// Matches Firefox, which formats headers like Chrome. Boundaries are
+    /// generated by [`GeckoGenerator`](struct.GeckoGenerator.html).
+    ///
+    #[inline]
+    pub fn firefox() -> Self {
+        Self {
+            boundary: BoundaryStyle::Gecko,
+            ..Self::chrome()
+        }
+    }
+
+    /// Matches `curl -F`, which formats headers like Chrome. Boundaries
+    /// are generated by [`CurlGenerator`](struct.CurlGenerator.html).
+    ///
+    #[inline]
+    pub fn curl() -> Self {
+        Self {
+            boundary: BoundaryStyle::Curl,
+            ..Self::chrome()
+        }
+    }
+
+    /// Sets the casing of header names.
+    ///
+    #[inline]
+    pub fn header_case(mut self, case: HeaderCase) -> Self {
+        self.header_case = case;
+        self
+    }
+
+    /// Sets whether the boundary is quoted in the content type of the
+    /// form.
+    ///
+    #[inline]
+    pub fn quote_boundary(mut self, quote: bool) -> Self {
+        self.quote_boundary = quote;
+        self
+    }
+
+    /// Sets whether parts whose content type is the default `text/plain`
+    /// omit their `Content-Type` header. Content types set explicitly are
+    /// always written out.
+    ///
+    /// [See](https://tools.ietf.org/html/rfc7578#section-4.4)
+    ///
+    #[inline]
+    pub fn omit_default_content_type(mut self, omit: bool) -> Self {
+        self.omit_default_content_type = omit;
+        self
+    }
+
+    #[inline]
+    pub(crate) fn quotes_boundary(&self) -> bool {
+        self.quote_boundary
+    }
+
+    #[inline]
+    pub(crate) fn omits_default_content_type(&self) -> bool {
+        self.omit_default_content_type
+    }
+
+    /// Generates a boundary like the client the profile matches.
+    ///
+    pub(crate) fn generate_boundary(&self) -> String {
+        match self.boundary {
+            BoundaryStyle::Random => RandomAsciiGenerator::generate_boundary(),
+            BoundaryStyle::WebKit => WebKitGenerator::generate_boundary(),
+            BoundaryStyle::Gecko => GeckoGenerator::generate_boundary(),
+            BoundaryStyle::Curl => CurlGenerator::generate_boundary(),
+        }
+    }
+
+    /// Formats a lowercase header name.
+    ///
+    pub(crate) fn header_name<'n>(&self, name: &'n str) -> Cow<'n, str> {
+        match self.header_case {
+            HeaderCase::Lower => Cow::Borrowed(name),
+            HeaderCase::Title => Cow::Owned(
+                name.split('-')
+                    .map(|word| match word {
+                        "md5" => "MD5".to_string(),
+                        _ => {
+                            let mut chars = word.chars();
+                            match chars.next() {
+                                Some(first) => first.to_uppercase().chain(chars).collect(),
+                                None => String::new(),
+                            }
+                        }
+                    })
+                    .collect::<Vec<_>>()
+                    .join("-"),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{HeaderCase, Profile};
+
+    #[test]
+    fn test_header_name() {
+        let title = Profile::default().header_case(HeaderCase::Title);
+        assert_eq!(
+            "Content-Disposition",
+            title.header_name("content-disposition")
+        );
+        assert_eq!("Content-MD5", title.header_name("content-md5"));
+        assert_eq!(
+            "content-type",
+            Profile::default().header_name("content-type")
+        );
+    }
+}
@@ -0,0 +1,657 @@
+// Copyright 2018 rust-multipart-rfc7578 Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+//
+
+use crate::error::Error;
+use crate::part::PartKind;
+use mime::{self, Mime};
+use std::{
+    fs::{self, File},
+    io::{self, Cursor, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+/// Where the data of a part comes from.
+///
+/// Sources are added to a form with
+/// [`Form::add_source`](struct.Form.html#method.add_source). The reader is
+/// only produced once the form reaches the part, so errors opening it are
+/// reported by the form, naming the part.
+///
+/// It is implemented for `String`, `Arc<[u8]>`, `Bytes` with the `bytes`
+/// feature and `File`, and by [`PathSource`](struct.PathSource.html),
+/// [`ReaderSource`](struct.ReaderSource.html) and
+/// [`SeekSource`](struct.SeekSource.html).
+///
+/// # Examples
+///
+/// ```
+/// use multipart_rfc7578::{Form, PartSource};
+/// use std::io::{self, Read};
+///
+/// struct Zeros(u64);
+///
+/// impl<'a> PartSource<'a> for Zeros {
+///     fn length(&self) -> Option<u64> {
+///         Some(self.0)
+///     }
+///
+///     fn replay(&self) -> Option<Box<dyn 'a + PartSource<'a>>> {
+///         Some(Box::new(Zeros(self.0)))
+///     }
+///
+///     fn into_reader(self: Box<Self>) -> io::Result<Box<dyn 'a + Read + Send>> {
+///         Ok(Box::new(io::repeat(0).take(self.0)))
+///     }
+/// }
+///
+/// let mut form = Form::default();
+///
+/// form.add_source("zeros", Zeros(1024), Some("zeros.bin"), None);
+/// assert!(form.try_clone().is_ok());
+/// ```
+///
+pub trait PartSource<'a>: Send {
+    /// Returns the exact length of the data, if it is known up front. It
+    /// is used for the content length of the form, and the data is checked
+    /// against it when the part is written out.
+    ///
+    fn length(&self) -> Option<u64> {
+        None
+    }
+
+    /// Returns the content type of the part, unless one is given when it
+    /// is added.
+    ///
+    fn content_type(&self) -> Mime {
+        mime::APPLICATION_OCTET_STREAM
+    }
+
+    /// Returns the filename of the part, unless one is given when it is
+    /// added.
+    ///
+    fn filename(&self) -> Option<String> {
+        None
+    }
+
+    /// Returns where the data comes from, as reported by
+    /// [`PartInfo::kind`](struct.PartInfo.html#method.kind).
+    ///
+    fn kind(&self) -> PartKind {
+        PartKind::Reader
+    }
+
+    /// Returns a source producing the same data again, so the form can be
+    /// replayed. Sources that can only be read once return `None`.
+    ///
+    fn replay(&self) -> Option<Box<dyn 'a + PartSource<'a>>> {
+        None
+    }
+
+    /// Returns the data as text, if the part is a text field. Text fields
+    /// are shown in previews and exported to curl as `--form-string`.
+    ///
+    fn text(&self) -> Option<&str> {
+        None
+    }
+
+    /// Returns the path of the file the data is read from, if the file is
+    /// only opened by `into_reader`. Files with a path are exported to
+    /// curl as `@path`, and checked against their length before they are
+    /// read if the form detects file changes.
+    ///
+    fn path(&self) -> Option<&Path> {
+        None
+    }
+
+    /// Produces the reader of the data.
+    ///
+    fn into_reader(self: Box<Self>) -> io::Result<Box<dyn 'a + Read + Send>>;
+}
+
+/// A reader that opens its source on the first read.
+///
+pub(crate) struct LazyReader<'a> {
+    source: Option<Box<dyn 'a + PartSource<'a>>>,
+    detect_file_changes: bool,
+    read: Option<Box<dyn 'a + Read + Send>>,
+}
+
+impl<'a> LazyReader<'a> {
+    /// Creates a reader of `source`. If `detect_file_changes` is set, a
+    /// source with a path and a length fails to open once the file no
+    /// longer has that length.
+    ///
+    #[inline]
+    pub(crate) fn new(source: Box<dyn 'a + PartSource<'a>>, detect_file_changes: bool) -> Self {
+        Self {
+            source: Some(source),
+            detect_file_changes,
+            read: None,
+        }
+    }
+}
+
+impl<'a> Read for LazyReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(source) = self.source.take() {
+            if self.detect_file_changes {
+                if let (Some(path), Some(expected)) = (source.path(), source.length()) {
+                    check_length(path, expected)?;
+                }
+            }
+            self.read = Some(source.into_reader()?);
+        }
+        match self.read {
+            Some(ref mut read) => read.read(buf),
+            None => Ok(0),
+        }
+    }
+}
+
+/// Fails if the file at `path` is no longer `expected` bytes long.
+///
+fn check_length(path: &Path, expected: u64) -> io::Result<()> {
+    let actual = fs::metadata(path)
+        .map_err(|source| Error::Open {
+            path: path.to_path_buf(),
+            source,
+        })?
+        .len();
+    if actual != expected {
+        return Err(Error::FileChanged {
+            path: path.to_path_buf(),
+            expected,
+            actual,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+impl<'a> PartSource<'a> for String {
+    #[inline]
+    fn length(&self) -> Option<u64> {
+        Some(self.len() as u64)
+    }
+
+    #[inline]
+    fn content_type(&self) -> Mime {
+        mime::TEXT_PLAIN
+    }
+
+    #[inline]
+    fn kind(&self) -> PartKind {
+        PartKind::Text
+    }
+
+    #[inline]
+    fn replay(&self) -> Option<Box<dyn 'a + PartSource<'a>>> {
+        Some(Box::new(self.clone()))
+    }
+
+    #[inline]
+    fn text(&self) -> Option<&str> {
+        Some(self)
+    }
+
+    #[inline]
+    fn into_reader(self: Box<Self>) -> io::Result<Box<dyn 'a + Read + Send>> {
+        Ok(Box::new(Cursor::new(self.into_bytes())))
+    }
+}
+
+impl<'a> PartSource<'a> for Arc<[u8]> {
+    #[inline]
+    fn length(&self) -> Option<u64> {
+        Some(self.len() as u64)
+    }
+
+    #[inline]
+    fn kind(&self) -> PartKind {
+        PartKind::Bytes
+    }
+
+    #[inline]
+    fn replay(&self) -> Option<Box<dyn 'a + PartSource<'a>>> {
+        Some(Box::new(self.clone()))
+    }
+
+    #[inline]
+    fn into_reader(self: Box<Self>) -> io::Result<Box<dyn 'a + Read + Send>> {
+        Ok(Box::new(Cursor::new(*self)))
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl<'a> PartSource<'a> for bytes::Bytes {
+    #[inline]
+    fn length(&self) -> Option<u64> {
+        Some(self.len() as u64)
+    }
+
+    #[inline]
+    fn kind(&self) -> PartKind {
+        PartKind::Bytes
+    }
+
+    #[inline]
+    fn replay(&self) -> Option<Box<dyn 'a + PartSource<'a>>> {
+        Some(Box::new(self.clone()))
+    }
+
+    #[inline]
+    fn into_reader(self: Box<Self>) -> io::Result<Box<dyn 'a + Read + Send>> {
+        Ok(Box::new(Cursor::new(*self)))
+    }
+}
+
+/// An open regular file is read from its start, and has the length of
+/// the file. It is replayed through a duplicate of its handle, which is
+/// rewound when the replay is created. The handles share their position,
+/// so a replay must not be read while the original is.
+///
+/// Other files, such as pipes, are read once from their current position
+/// and have no known length.
+///
+impl<'a> PartSource<'a> for File {
+    #[inline]
+    fn length(&self) -> Option<u64> {
+        self.metadata()
+            .ok()
+            .filter(|meta| meta.is_file())
+            .map(|meta| meta.len())
+    }
+
+    #[inline]
+    fn kind(&self) -> PartKind {
+        PartKind::File
+    }
+
+    #[inline]
+    fn replay(&self) -> Option<Box<dyn 'a + PartSource<'a>>> {
+        if !is_regular(self) {
+            return None;
+        }
+        let mut file = self.try_clone().ok()?;
+        file.seek(SeekFrom::Start(0)).ok()?;
+        Some(Box::new(file))
+    }
+
+    #[inline]
+    fn into_reader(mut self: Box<Self>) -> io::Result<Box<dyn 'a + Read + Send>> {
+        if is_regular(&self) {
+            self.seek(SeekFrom::Start(0))?;
+        }
+        Ok(self)
+    }
+}
+
+/// Returns true if the file is a regular file, which can be rewound.
+///
+#[inline]
+fn is_regular(file: &File) -> bool {
+    file.metadata().map(|meta| meta.is_file()).unwrap_or(false)
+}
+
+/// A file that is opened from its path when the part is written out, and
+/// closed once it has been read. Its filename is the last component of
+/// the path.
+///
+/// # Examples
+///
+/// ```
+/// use multipart_rfc7578::{Form, PathSource};
+///
+/// let mut form = Form::default();
+/// let source = PathSource::new("Cargo.toml").unwrap();
+///
+/// form.add_source::<_, String, _>("manifest", source, None, None);
+/// ```
+///
+#[derive(Clone, Debug)]
+pub struct PathSource {
+    path: PathBuf,
+    length: u64,
+}
+
+impl PathSource {
+    /// Reads the metadata of a file. It fails if the file cannot be
+    /// accessed, or if the path is not a file.
+    ///
+    pub fn new<P>(path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref().to_path_buf();
+        match fs::metadata(&path) {
+            // If the path is not a file, it can't be uploaded because there
+            // is no content.
+            //
+            Ok(ref meta) if !meta.is_file() => Err(Error::NotAFile { path }),
+            Ok(meta) => Ok(Self {
+                path,
+                length: meta.len(),
+            }),
+            Err(source) => Err(Error::Open { path, source }),
+        }
+    }
+}
+
+impl<'a> PartSource<'a> for PathSource {
+    #[inline]
+    fn length(&self) -> Option<u64> {
+        Some(self.length)
+    }
+
+    #[inline]
+    fn filename(&self) -> Option<String> {
+        self.path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+    }
+
+    #[inline]
+    fn kind(&self) -> PartKind {
+        PartKind::File
+    }
+
+    #[inline]
+    fn replay(&self) -> Option<Box<dyn 'a + PartSource<'a>>> {
+        Some(Box::new(self.clone()))
+    }
+
+    #[inline]
+    fn path(&self) -> Option<&Path> {
+        Some(&self.path)
+    }
+
+    #[inline]
+    fn into_reader(self: Box<Self>) -> io::Result<Box<dyn 'a + Read + Send>> {
+        let file = File::open(&self.path).map_err(|source| Error::Open {
+            path: self.path.clone(),
+            source,
+        })?;
+        Ok(Box::new(file))
+    }
+}
+
+/// A reader, which can only be read once.
+///
+/// # Examples
+///
+/// ```
+/// use multipart_rfc7578::{Form, ReaderSource};
+/// use std::io::Cursor;
+///
+/// let mut form = Form::default();
+/// let source = ReaderSource::new(Cursor::new("Hello World!"), Some(12));
+///
+/// form.add_source("input", source, Some("input.txt"), None);
+/// ```
+///
+pub struct ReaderSource<R> {
+    read: R,
+    length: Option<u64>,
+}
+
+impl<R> ReaderSource<R> {
+    /// Creates a source reading `length` bytes from `read`, if the length
+    /// is known.
+    ///
+    #[inline]
+    pub fn new(read: R, length: Option<u64>) -> Self {
+        Self { read, length }
+    }
+}
+
+impl<'a, R> PartSource<'a> for ReaderSource<R>
+where
+    R: 'a + Read + Send,
+{
+    #[inline]
+    fn length(&self) -> Option<u64> {
+        self.length
+    }
+
+    #[inline]
+    fn into_reader(self: Box<Self>) -> io::Result<Box<dyn 'a + Read + Send>> {
+        Ok(Box::new(self.read))
+    }
+}
+
+/// A reader that is shared between replays, and rewound to the position
+/// it had when the source was created.
+///
+/// # Examples
+///
+/// ```
+/// use multipart_rfc7578::{Form, SeekSource};
+/// use std::io::Cursor;
+///
+/// let mut form = Form::default();
+/// let source = SeekSource::new(Cursor::new("Hello World!"), Some(12)).unwrap();
+///
+/// form.add_source("input", source, Some("input.txt"), None);
+/// assert!(form.try_clone().is_ok());
+/// ```
+///
+pub struct SeekSource<R> {
+    read: Arc<Mutex<R>>,
+    start: u64,
+    length: Option<u64>,
+}
+
+impl<R> SeekSource<R>
+where
+    R: Seek,
+{
+    /// Creates a source reading from the current position of `read`, so
+    /// `length` should be the number of bytes from there to the end.
+    ///
+    pub fn new(mut read: R, length: Option<u64>) -> io::Result<Self> {
+        let start = read.stream_position()?;
+        Ok(Self {
+            read: Arc::new(Mutex::new(read)),
+            start,
+            length,
+        })
+    }
+
+    /// Creates a source reading from the current position of `read` to
+    /// its end. The length is measured by seeking to the end and back.
+    ///
+    pub fn measured(mut read: R) -> io::Result<Self> {
+        let start = read.stream_position()?;
+        let end = read.seek(SeekFrom::End(0))?;
+        read.seek(SeekFrom::Start(start))?;
+        Self::new(read, Some(end.saturating_sub(start)))
+    }
+}
+
+impl<'a, R> PartSource<'a> for SeekSource<R>
+where
+    R: 'a + Read + Seek + Send,
+{
+    #[inline]
+    fn length(&self) -> Option<u64> {
+        self.length
+    }
+
+    #[inline]
+    fn kind(&self) -> PartKind {
+        PartKind::Seek
+    }
+
+    #[inline]
+    fn replay(&self) -> Option<Box<dyn 'a + PartSource<'a>>> {
+        Some(Box::new(SeekSource {
+            read: self.read.clone(),
+            start: self.start,
+            length: self.length,
+        }))
+    }
+
+    fn into_reader(self: Box<Self>) -> io::Result<Box<dyn 'a + Read + Send>> {
+        lock(&self.read).seek(SeekFrom::Start(self.start))?;
+        Ok(Box::new(Shared(self.read)))
+    }
+}
+
+/// A reader shared between replays.
+///
+struct Shared<R>(Arc<Mutex<R>>);
+
+impl<R: Read> Read for Shared<R> {
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        lock(&self.0).read(buf)
+    }
+}
+
+/// Locks a shared reader. A reader poisoned by a panic is still usable,
+/// since the next replay seeks it back into a known position.
+///
+#[inline]
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    match mutex.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+/// Creates a new reader every time a part is replayed.
+///
+pub(crate) type ReadFactory<'a> =
+    Arc<dyn Fn() -> io::Result<Box<dyn 'a + Read + Send>> + Send + Sync + 'a>;
+
+/// A user supplied function creating a new reader for every replay.
+///
+pub(crate) struct FactorySource<'a> {
+    factory: ReadFactory<'a>,
+    length: Option<u64>,
+}
+
+impl<'a> FactorySource<'a> {
+    #[inline]
+    pub(crate) fn new(factory: ReadFactory<'a>, length: Option<u64>) -> Self {
+        Self { factory, length }
+    }
+}
+
+impl<'a> PartSource<'a> for FactorySource<'a> {
+    #[inline]
+    fn length(&self) -> Option<u64> {
+        self.length
+    }
+
+    #[inline]
+    fn kind(&self) -> PartKind {
+        PartKind::Factory
+    }
+
+    #[inline]
+    fn replay(&self) -> Option<Box<dyn 'a + PartSource<'a>>> {
+        Some(Box::new(FactorySource::new(
+            self.factory.clone(),
+            self.length,
+        )))
+    }
+
+    #[inline]
+    fn into_reader(self: Box<Self>) -> io::Result<Box<dyn 'a + Read + Send>> {
+        (self.factory)()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LazyReader, PartSource, PathSource, SeekSource};
+    use crate::{Error, PartKind};
+    use std::{
+        fs::File,
+        io::{Cursor, Read},
+    };
+
+    fn read_all<'a>(source: Box<dyn 'a + PartSource<'a>>) -> Vec<u8> {
+        let mut out = vec![];
+        LazyReader::new(source, false)
+            .read_to_end(&mut out)
+            .unwrap();
+        out
+    }
+
+    #[test]
+    fn test_replay() {
+        let mut read = Cursor::new("skip, data");
+        read.set_position(6);
+        let source = SeekSource::new(read, Some(4)).unwrap();
+        let replay = source.replay().unwrap();
+        assert_eq!(b"data", &read_all(Box::new(source))[..]);
+        assert_eq!(b"data", &read_all(replay)[..]);
+
+        let file = File::open("Cargo.toml").unwrap();
+        assert_eq!(PartKind::File, file.kind());
+        let replay = file.replay().unwrap();
+        let first = read_all(Box::new(file));
+        assert_eq!(first.len() as u64, replay.length().unwrap());
+        assert_eq!(first, read_all(replay));
+
+        let path = std::env::temp_dir().join(format!(
+            "multipart-rfc7578-empty-{}.txt",
+            std::process::id()
+        ));
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&path)
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(Some(0), file.length());
+        assert!(read_all(file.replay().unwrap()).is_empty());
+    }
+
+    #[test]
+    fn test_path_source() {
+        let source = PathSource::new("Cargo.toml").unwrap();
+        assert_eq!(Some("Cargo.toml".to_string()), source.filename());
+        assert_eq!(
+            std::fs::read("Cargo.toml").unwrap(),
+            read_all(Box::new(source))
+        );
+
+        match PathSource::new("src") {
+            Err(Error::NotAFile { .. }) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+        match PathSource::new("missing") {
+            Err(Error::Open { .. }) => (),
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_special_files() {
+        use std::process::{Command, Stdio};
+
+        let mut child = Command::new("echo")
+            .arg("piped")
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stdout = child.stdout.take().unwrap();
+        let pipe = File::from(std::os::fd::OwnedFd::from(stdout));
+        assert_eq!(None, pipe.length());
+        assert!(pipe.replay().is_none());
+        assert_eq!(b"piped\n", &read_all(Box::new(pipe))[..]);
+        child.wait().unwrap();
+    }
+}
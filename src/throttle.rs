@@ -0,0 +1,235 @@
+// Copyright 2018 rust-multipart-rfc7578 Developers
+//
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+//
+
+use futures::Future;
+use std::{
+    fmt, io,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// A future that resolves once a deadline is reached.
+///
+pub type Delay = Box<dyn Future<Item = (), Error = io::Error> + Send>;
+
+/// The source of time used by a [`RateLimiter`](struct.RateLimiter.html).
+///
+/// With the `tokio-timer` feature, [`TokioClock`](struct.TokioClock.html)
+/// uses the system time and the tokio timer. Tests can implement this
+/// to drive a limiter without waiting.
+///
+pub trait Clock: Send + Sync {
+    /// Returns the current time.
+    ///
+    fn now(&self) -> Instant;
+
+    /// Returns a future that resolves at `deadline`.
+    ///
+    fn delay(&self, deadline: Instant) -> Delay;
+}
+
+/// A clock using the system time and the tokio timer.
+///
+#[cfg(feature = "tokio-timer")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TokioClock;
+
+#[cfg(feature = "tokio-timer")]
+impl Clock for TokioClock {
+    #[inline]
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    #[inline]
+    fn delay(&self, deadline: Instant) -> Delay {
+        Box::new(tokio_timer::Delay::new(deadline).map_err(io::Error::other))
+    }
+}
+
+/// Limits the rate at which bodies emit bytes, using a token bucket.
+///
+/// The bucket holds up to `burst` bytes and refills at `bytes_per_second`.
+/// A chunk that does not fit in the bucket is held back until it does.
+/// Clones share the same bucket, so one limiter can cap the combined rate
+/// of many concurrent bodies.
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(feature = "tokio-timer")]
+/// # fn main() {
+/// use multipart_rfc7578::{Body, Form, RateLimiter};
+///
+/// let limiter = RateLimiter::new(1024 * 1024, 64 * 1024);
+/// let mut form = Form::default();
+///
+/// form.add_text("text", "Hello World!");
+/// let body = Body::builder().rate_limiter(limiter.clone()).build(form);
+/// # }
+/// # #[cfg(not(feature = "tokio-timer"))]
+/// # fn main() {
+/// # }
+/// ```
+///
+#[derive(Clone)]
+pub struct RateLimiter {
+    bucket: Arc<Mutex<Bucket>>,
+    clock: Arc<dyn Clock>,
+}
+
+struct Bucket {
+    bytes_per_second: f64,
+    burst: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    /// Creates a limiter using the tokio timer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bytes_per_second` is zero.
+    ///
+    #[cfg(feature = "tokio-timer")]
+    #[inline]
+    pub fn new(bytes_per_second: u64, burst: u64) -> Self {
+        Self::with_clock(bytes_per_second, burst, TokioClock)
+    }
+
+    /// Creates a limiter using the specified clock.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bytes_per_second` is zero.
+    ///
+    pub fn with_clock<C>(bytes_per_second: u64, burst: u64, clock: C) -> Self
+    where
+        C: Clock + 'static,
+    {
+        assert!(bytes_per_second > 0, "rate must be greater than zero");
+        let bucket = Bucket {
+            bytes_per_second: bytes_per_second as f64,
+            burst: burst as f64,
+            tokens: burst as f64,
+            updated: clock.now(),
+        };
+        Self {
+            bucket: Arc::new(Mutex::new(bucket)),
+            clock: Arc::new(clock),
+        }
+    }
+
+    /// Takes `bytes` out of the bucket, and returns when they may be
+    /// emitted if the bucket did not hold enough.
+    ///
+    /// The bucket may go into debt, so chunks larger than the burst are
+    /// still emitted, and later chunks pay for them.
+    ///
+    pub(crate) fn reserve(&self, bytes: usize) -> Option<Instant> {
+        let now = self.clock.now();
+        let mut bucket = match self.bucket.lock() {
+            Ok(bucket) => bucket,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let elapsed = now.saturating_duration_since(bucket.updated);
+        bucket.tokens =
+            (bucket.tokens + elapsed.as_secs_f64() * bucket.bytes_per_second).min(bucket.burst);
+        bucket.updated = now;
+        bucket.tokens -= bytes as f64;
+        if bucket.tokens >= 0.0 {
+            None
+        } else {
+            let wait = -bucket.tokens / bucket.bytes_per_second;
+            Some(now + Duration::from_secs_f64(wait))
+        }
+    }
+
+    #[inline]
+    pub(crate) fn delay(&self, deadline: Instant) -> Delay {
+        self.clock.delay(deadline)
+    }
+}
+
+impl fmt::Debug for RateLimiter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bucket = match self.bucket.lock() {
+            Ok(bucket) => bucket,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        f.debug_struct("RateLimiter")
+            .field("bytes_per_second", &bucket.bytes_per_second)
+            .field("burst", &bucket.burst)
+            .field("tokens", &bucket.tokens)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::{Clock, Delay, RateLimiter};
+    use futures::future;
+    use std::{
+        sync::{Arc, Mutex},
+        time::{Duration, Instant},
+    };
+
+    /// A clock that jumps to the deadline of every delay.
+    ///
+    #[derive(Clone)]
+    pub(crate) struct MockClock(pub(crate) Arc<Mutex<Instant>>);
+
+    impl MockClock {
+        pub(crate) fn new() -> Self {
+            MockClock(Arc::new(Mutex::new(Instant::now())))
+        }
+
+        pub(crate) fn advance(&self, duration: Duration) {
+            *self.0.lock().unwrap() += duration;
+        }
+    }
+
+    impl Clock for MockClock {
+        fn now(&self) -> Instant {
+            *self.0.lock().unwrap()
+        }
+
+        fn delay(&self, deadline: Instant) -> Delay {
+            let mut now = self.0.lock().unwrap();
+            if *now < deadline {
+                *now = deadline;
+            }
+            Box::new(future::ok(()))
+        }
+    }
+
+    #[test]
+    fn test_reserve() {
+        let clock = MockClock::new();
+        let start = clock.now();
+        let limiter = RateLimiter::with_clock(100, 50, clock.clone());
+        assert_eq!(None, limiter.reserve(50));
+        assert_eq!(
+            Some(start + Duration::from_millis(250)),
+            limiter.reserve(25)
+        );
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(None, limiter.reserve(50));
+    }
+
+    #[test]
+    fn test_shared() {
+        let clock = MockClock::new();
+        let start = clock.now();
+        let limiter = RateLimiter::with_clock(100, 100, clock);
+        let other = limiter.clone();
+        assert_eq!(None, limiter.reserve(100));
+        assert_eq!(Some(start + Duration::from_secs(1)), other.reserve(100));
+    }
+}